@@ -1,8 +1,23 @@
-use std::fmt::Display;
+use std::fmt::{Display, Write};
 
-use serde::de::{self, Unexpected};
+use serde::{
+    de::{
+        self, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, Unexpected,
+        VariantAccess,
+    },
+    Deserialize,
+};
 
-use super::EOT;
+use crate::{
+    message::{self, English, Localize, MessageCatalog},
+    varint::{self, DecodeError},
+};
+
+use super::{tag, EncodingConfig, LengthEncoding, StringEncoding, EOT, MAGIC, VERSION};
+
+/// Nesting allowed by default, deep enough for real payloads while keeping the recursion far from
+/// the stack limit.
+const DEFAULT_MAX_DEPTH: usize = 128;
 
 #[derive(Debug, PartialEq)]
 pub enum Error {
@@ -11,11 +26,86 @@ pub enum Error {
     WrongDeserializeType,
     EotNotFound,
     EmptyBuffer,
+    PayloadTooLarge,
+    InvalidVarint,
+    Io(String),
+    MissingHeader,
+    UnsupportedVersion(u8),
+    UnsupportedFlags(u8),
+    UnknownTag(u8),
+    DepthLimitExceeded,
+    /// `source` was raised at byte `offset` of the buffer, while deserializing the value at
+    /// `path` (e.g. `entries[2].name`, empty for the top-level value).
+    Located {
+        offset: usize,
+        path: String,
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// The error without the location it was raised at.
+    pub fn kind(&self) -> &Self {
+        match self {
+            Self::Located { source, .. } => source.kind(),
+            _ => self,
+        }
+    }
+}
+
+impl Localize for Error {
+    fn code(&self) -> &'static str {
+        match self.kind() {
+            Self::Custom(_) => "custom",
+            Self::DeserializeAny => "deserialize_any",
+            Self::WrongDeserializeType => "wrong_deserialize_type",
+            Self::EotNotFound => "eot_not_found",
+            Self::EmptyBuffer => "empty_buffer",
+            Self::PayloadTooLarge => "payload_too_large",
+            Self::InvalidVarint => "invalid_varint",
+            Self::Io(_) => "io",
+            Self::MissingHeader => "missing_header",
+            Self::UnsupportedVersion(_) => "unsupported_version",
+            Self::UnsupportedFlags(_) => "unsupported_flags",
+            Self::UnknownTag(_) => "unknown_tag",
+            Self::DepthLimitExceeded => "depth_limit_exceeded",
+            Self::Located { .. } => unreachable!(),
+        }
+    }
+
+    fn localize(&self, catalog: &dyn MessageCatalog) -> String {
+        match self {
+            Self::Located {
+                offset,
+                path,
+                source,
+            } => {
+                let key = if path.is_empty() {
+                    "located"
+                } else {
+                    "located_path"
+                };
+                let args = [source.localize(catalog), offset.to_string(), path.clone()];
+                message::localize(catalog, key, &args)
+            }
+            Self::Custom(arg) | Self::Io(arg) => {
+                message::localize(catalog, self.code(), std::slice::from_ref(arg))
+            }
+            Self::UnsupportedVersion(version) => {
+                message::localize(catalog, self.code(), &[version.to_string()])
+            }
+            Self::UnsupportedFlags(flags) => {
+                message::localize(catalog, self.code(), &[format!("{:#04x}", flags)])
+            }
+            Self::UnknownTag(tag) => message::localize(catalog, self.code(), &[tag.to_string()]),
+            _ => message::localize(catalog, self.code(), &[]),
+        }
+    }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        f.write_str(&self.localize(&English))
     }
 }
 
@@ -30,27 +120,191 @@ impl de::Error for Error {
     }
 }
 
+/// Reads values from the front of a buffer, consuming exactly the bytes each one occupies.
+///
+/// Unless [`EncodingConfig::raw`](field@EncodingConfig::raw) is set, the buffer starts with the
+/// format header, which records the encoding of the values. A missing or unsupported header is
+/// reported by every read.
+///
+/// The owned Deserializer reads a single value spanning the whole buffer, while `&mut Deserializer`
+/// (or [`Deserializer::read`]) leaves whatever follows the value to be read next.
 pub struct Deserializer<'a> {
+    input: &'a [u8],
     buffer: &'a [u8],
+    config: EncodingConfig,
+    header_pending: bool,
+    path: Vec<Segment<'a>>,
+    depth: usize,
+    max_depth: usize,
+}
+
+/// Step from a value into one nested in it, tracked to tell where errors were raised.
+#[derive(Debug, Clone, Copy)]
+enum Segment<'a> {
+    Field(&'a str),
+    Index(usize),
+    Variant(&'a str),
 }
 
 impl<'a> Deserializer<'a> {
     pub fn new(buffer: &'a [u8]) -> Self {
-        Self { buffer }
+        Self::with_config(buffer, EncodingConfig::default())
     }
 
-    unsafe fn deserialize_integer<Integer, const SIZE: usize, V>(
-        self,
-        visitor: &V,
-    ) -> Result<Integer, <Self as serde::Deserializer<'a>>::Error>
+    /// Only [`EncodingConfig::raw`](field@EncodingConfig::raw) is taken from `config` when the
+    /// buffer has a header, the rest of the encoding is read from it. Raw buffers must be read
+    /// with the configuration they were serialized with.
+    pub fn with_config(buffer: &'a [u8], config: EncodingConfig) -> Self {
+        let mut deserializer = Self {
+            input: buffer,
+            buffer,
+            config,
+            header_pending: !config.raw,
+            path: vec![],
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+        };
+        // The header is read right away so its encoding applies to every value, but an invalid
+        // one is left for the reads to report.
+        let _ = deserializer.check_header();
+        deserializer
+    }
+
+    /// Limits how deeply options, sequences, maps, structs and enum variants may nest, 128 levels
+    /// by default, so corrupt or hostile input can't overflow the stack.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Deserializes the next value from the buffer.
+    pub fn read<T>(&mut self) -> Result<T, Error>
     where
-        Integer: Sized,
-        V: serde::de::Visitor<'a>,
+        T: Deserialize<'a>,
     {
-        if !self.buffer[SIZE..].is_empty() {
+        self.check_header()
+            .and_then(|()| T::deserialize(&mut *self))
+            .map_err(|err| self.locate(err))
+    }
+
+    /// Number of bytes consumed so far.
+    pub fn position(&self) -> usize {
+        self.input.len() - self.buffer.len()
+    }
+
+    /// The encoding values are read with, as recorded by the header once it was read.
+    pub(crate) fn config(&self) -> EncodingConfig {
+        self.config
+    }
+
+    /// Bytes that were not consumed yet.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.buffer
+    }
+
+    pub fn end(&mut self) -> Result<(), Error> {
+        self.check_header()?;
+        if !self.buffer.is_empty() {
             return Err(Error::WrongDeserializeType);
         }
 
+        Ok(())
+    }
+
+    /// Attaches the current position and path to `err`, resetting the path for the next value.
+    fn locate(&mut self, err: Error) -> Error {
+        let segments = std::mem::take(&mut self.path);
+        if let Error::Located { .. } = err {
+            return err;
+        }
+
+        let mut path = String::new();
+        for segment in segments {
+            let _ = match segment {
+                Segment::Field(name) if path.is_empty() => write!(path, "{}", name),
+                Segment::Field(name) => write!(path, ".{}", name),
+                Segment::Index(index) => write!(path, "[{}]", index),
+                Segment::Variant(name) => write!(path, "::{}", name),
+            };
+        }
+
+        Error::Located {
+            offset: self.position(),
+            path,
+            source: Box::new(err),
+        }
+    }
+
+    /// Runs `f` with `segment` appended to the path. On error the path is left as is, so
+    /// [`Deserializer::locate`] can report it.
+    fn nested<T>(
+        &mut self,
+        segment: Segment<'a>,
+        f: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        self.path.push(segment);
+        let value = f(self)?;
+        self.path.pop();
+        Ok(value)
+    }
+
+    /// Runs `f` one nesting level deeper, failing once the limit is reached.
+    fn deeper<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, Error>) -> Result<T, Error> {
+        if self.depth >= self.max_depth {
+            return Err(Error::DepthLimitExceeded);
+        }
+
+        self.depth += 1;
+        let value = f(self);
+        self.depth -= 1;
+        value
+    }
+
+    /// Reads the format header if it wasn't yet. An invalid header stays pending, so every read
+    /// reports it instead of decoding the header bytes as values.
+    fn check_header(&mut self) -> Result<(), Error> {
+        if !self.header_pending {
+            return Ok(());
+        }
+
+        let rest = self
+            .buffer
+            .strip_prefix(MAGIC)
+            .ok_or(Error::MissingHeader)?;
+        let (&version, rest) = rest.split_first().ok_or(Error::MissingHeader)?;
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let (&flags, rest) = rest.split_first().ok_or(Error::MissingHeader)?;
+        self.config = self
+            .config
+            .with_flags(flags)
+            .ok_or(Error::UnsupportedFlags(flags))?;
+
+        self.buffer = rest;
+        self.header_pending = false;
+        Ok(())
+    }
+
+    pub(super) fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        self.check_header()?;
+        if self.buffer.len() < len {
+            return Err(Error::EmptyBuffer);
+        }
+
+        let (value, rest) = self.buffer.split_at(len);
+        self.buffer = rest;
+        Ok(value)
+    }
+
+    fn deserialize_array<'de, const SIZE: usize, V>(
+        &mut self,
+        visitor: &V,
+    ) -> Result<[u8; SIZE], Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        self.check_header()?;
         let value = self
             .buffer
             .get(0..SIZE)
@@ -59,44 +313,181 @@ impl<'a> Deserializer<'a> {
                 visitor,
             ))?;
         let value = <[u8; SIZE]>::try_from(value).map_err(<Error as de::Error>::custom)?;
+        self.buffer = &self.buffer[SIZE..];
 
-        Ok(std::mem::transmute_copy::<[u8; SIZE], Integer>(&value))
+        Ok(value)
     }
 
-    fn deserialize_str(self) -> Result<&'a str, <Self as serde::Deserializer<'a>>::Error> {
-        if self.buffer.is_empty() || self.buffer[self.buffer.len() - 1] != EOT {
-            return Err(Error::EotNotFound);
+    /// Lengths are written as u64 (or a varint of at most 64 bits) so buffers stay portable
+    /// between 32 and 64-bit targets.
+    pub(super) fn parse_len(&mut self) -> Result<usize, Error> {
+        let len = match self.config.lengths {
+            LengthEncoding::Fixed => {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(self.take(8)?);
+                u64::from_le_bytes(bytes)
+            }
+            LengthEncoding::Varint => self.parse_varint()?,
+        };
+        usize::try_from(len).map_err(|_| Error::PayloadTooLarge)
+    }
+
+    fn parse_varint(&mut self) -> Result<u64, Error> {
+        self.check_header()?;
+        varint::decode(&mut self.buffer).map_err(|error| match error {
+            DecodeError::Truncated => Error::EmptyBuffer,
+            DecodeError::Overflow => Error::InvalidVarint,
+        })
+    }
+
+    fn expect_len<'de, V>(&mut self, len: usize, visitor: &V) -> Result<(), Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        let stored_len = self.parse_len()?;
+        if stored_len != len {
+            return Err(de::Error::invalid_length(stored_len, visitor));
         }
 
-        std::str::from_utf8(&self.buffer[..self.buffer.len() - 1]).map_err(de::Error::custom)
+        Ok(())
+    }
+
+    fn parse_bool<'de, V>(&mut self, visitor: &V) -> Result<bool, Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        match u8::from_le_bytes(self.deserialize_array(visitor)?) {
+            0 => Ok(false),
+            1 => Ok(true),
+            n => Err(de::Error::invalid_value(
+                Unexpected::Unsigned(n as u64),
+                visitor,
+            )),
+        }
+    }
+
+    fn parse_char<'de, V>(&mut self, visitor: &V) -> Result<char, Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        let value = u32::from_le_bytes(self.deserialize_array(visitor)?);
+        char::from_u32(value).ok_or(de::Error::invalid_value(
+            Unexpected::Unsigned(value as u64),
+            visitor,
+        ))
+    }
+
+    /// The next value if it's a tagged string, without consuming it.
+    fn peek_str(&mut self) -> Option<&'a str> {
+        let buffer = self.buffer;
+        let value = match self.take(1) {
+            Ok([tag::STR]) => self.parse_str().ok(),
+            _ => None,
+        };
+        self.buffer = buffer;
+        value
+    }
+
+    fn parse_bytes(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.parse_len()?;
+        self.take(len)
+    }
+
+    fn parse_str(&mut self) -> Result<&'a str, Error> {
+        self.check_header()?;
+        let value = match self.config.strings {
+            StringEncoding::Terminated => {
+                let len = self
+                    .buffer
+                    .iter()
+                    .position(|&byte| byte == EOT)
+                    .ok_or(Error::EotNotFound)?;
+                let value = self.take(len)?;
+                self.take(1)?;
+                value
+            }
+            StringEncoding::LengthPrefixed => {
+                let len = self.parse_len()?;
+                self.take(len)?
+            }
+        };
+
+        std::str::from_utf8(value).map_err(de::Error::custom)
     }
 }
 
-impl<'de, 'a: 'de> serde::Deserializer<'de> for Deserializer<'a> {
+impl<'de, 'a: 'de> serde::Deserializer<'de> for &mut Deserializer<'a> {
     type Error = Error;
 
-    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    /// Only possible in self-describing mode, where every value starts with its type tag.
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        Err(Error::DeserializeAny)
+        if !self.config.self_describing {
+            return Err(Error::DeserializeAny);
+        }
+
+        macro_rules! visit {
+            ($visit:ident, $decode:expr) => {{
+                let value = $decode(self.deserialize_array(&visitor)?);
+                visitor.$visit(value)
+            }};
+        }
+
+        match self.take(1)?[0] {
+            tag::UNIT => visitor.visit_unit(),
+            tag::BOOL => {
+                let value = self.parse_bool(&visitor)?;
+                visitor.visit_bool(value)
+            }
+            tag::I8 => visit!(visit_i8, i8::from_le_bytes),
+            tag::I16 => visit!(visit_i16, i16::from_le_bytes),
+            tag::I32 => visit!(visit_i32, i32::from_le_bytes),
+            tag::I64 => visit!(visit_i64, i64::from_le_bytes),
+            tag::I128 => visit!(visit_i128, i128::from_le_bytes),
+            tag::U8 => visit!(visit_u8, u8::from_le_bytes),
+            tag::U16 => visit!(visit_u16, u16::from_le_bytes),
+            tag::U32 => visit!(visit_u32, u32::from_le_bytes),
+            tag::U64 => visit!(visit_u64, u64::from_le_bytes),
+            tag::U128 => visit!(visit_u128, u128::from_le_bytes),
+            tag::F32 => visit!(visit_f32, |bytes| f32::from_bits(u32::from_le_bytes(bytes))),
+            tag::F64 => visit!(visit_f64, |bytes| f64::from_bits(u64::from_le_bytes(bytes))),
+            tag::CHAR => {
+                let value = self.parse_char(&visitor)?;
+                visitor.visit_char(value)
+            }
+            tag::STR => visitor.visit_borrowed_str(self.parse_str()?),
+            tag::BYTES => visitor.visit_borrowed_bytes(self.parse_bytes()?),
+            tag::NONE => visitor.visit_none(),
+            tag::SOME => self.deeper(|deserializer| visitor.visit_some(deserializer)),
+            tag::SEQ => {
+                let len = self.parse_len()?;
+                self.deeper(|deserializer| visitor.visit_seq(Access::new(deserializer, len)))
+            }
+            tag::MAP => {
+                let len = self.parse_len()?;
+                self.deeper(|deserializer| visitor.visit_map(Access::new(deserializer, len)))
+            }
+            tag::UNSIZED_SEQ => {
+                self.deeper(|deserializer| visitor.visit_seq(Access::terminated(deserializer)))
+            }
+            tag::UNSIZED_MAP => {
+                self.deeper(|deserializer| visitor.visit_map(Access::terminated(deserializer)))
+            }
+            tag => Err(Error::UnknownTag(tag)),
+        }
     }
 
     fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        const SIZE: usize = std::mem::size_of::<u8>();
-        let value = match unsafe { self.deserialize_integer::<u8, SIZE, V>(&visitor) }? {
-            0 => false,
-            1 => true,
-            n => {
-                return Err(de::Error::invalid_value(
-                    Unexpected::Unsigned(n as u64),
-                    &visitor,
-                ))
-            }
-        };
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        let value = self.parse_bool(&visitor)?;
         visitor.visit_bool(value)
     }
 
@@ -104,8 +495,11 @@ impl<'de, 'a: 'de> serde::Deserializer<'de> for Deserializer<'a> {
     where
         V: serde::de::Visitor<'de>,
     {
-        const SIZE: usize = std::mem::size_of::<i8>();
-        let value = unsafe { self.deserialize_integer::<i8, SIZE, V>(&visitor) }?;
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        let value = i8::from_le_bytes(self.deserialize_array(&visitor)?);
         visitor.visit_i8(value)
     }
 
@@ -113,8 +507,11 @@ impl<'de, 'a: 'de> serde::Deserializer<'de> for Deserializer<'a> {
     where
         V: serde::de::Visitor<'de>,
     {
-        const SIZE: usize = std::mem::size_of::<i16>();
-        let value = unsafe { self.deserialize_integer::<i16, SIZE, V>(&visitor) }?;
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        let value = i16::from_le_bytes(self.deserialize_array(&visitor)?);
         visitor.visit_i16(value)
     }
 
@@ -122,8 +519,11 @@ impl<'de, 'a: 'de> serde::Deserializer<'de> for Deserializer<'a> {
     where
         V: serde::de::Visitor<'de>,
     {
-        const SIZE: usize = std::mem::size_of::<i32>();
-        let value = unsafe { self.deserialize_integer::<i32, SIZE, V>(&visitor) }?;
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        let value = i32::from_le_bytes(self.deserialize_array(&visitor)?);
         visitor.visit_i32(value)
     }
 
@@ -131,17 +531,35 @@ impl<'de, 'a: 'de> serde::Deserializer<'de> for Deserializer<'a> {
     where
         V: serde::de::Visitor<'de>,
     {
-        const SIZE: usize = std::mem::size_of::<i64>();
-        let value = unsafe { self.deserialize_integer::<i64, SIZE, V>(&visitor) }?;
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        let value = i64::from_le_bytes(self.deserialize_array(&visitor)?);
         visitor.visit_i64(value)
     }
 
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        let value = i128::from_le_bytes(self.deserialize_array(&visitor)?);
+        visitor.visit_i128(value)
+    }
+
     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        const SIZE: usize = std::mem::size_of::<u8>();
-        let value = unsafe { self.deserialize_integer::<u8, SIZE, V>(&visitor) }?;
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        let value = u8::from_le_bytes(self.deserialize_array(&visitor)?);
         visitor.visit_u8(value)
     }
 
@@ -149,8 +567,11 @@ impl<'de, 'a: 'de> serde::Deserializer<'de> for Deserializer<'a> {
     where
         V: serde::de::Visitor<'de>,
     {
-        const SIZE: usize = std::mem::size_of::<u16>();
-        let value = unsafe { self.deserialize_integer::<u16, SIZE, V>(&visitor) }?;
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        let value = u16::from_le_bytes(self.deserialize_array(&visitor)?);
         visitor.visit_u16(value)
     }
 
@@ -158,8 +579,11 @@ impl<'de, 'a: 'de> serde::Deserializer<'de> for Deserializer<'a> {
     where
         V: serde::de::Visitor<'de>,
     {
-        const SIZE: usize = std::mem::size_of::<u32>();
-        let value = unsafe { self.deserialize_integer::<u32, SIZE, V>(&visitor) }?;
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        let value = u32::from_le_bytes(self.deserialize_array(&visitor)?);
         visitor.visit_u32(value)
     }
 
@@ -167,17 +591,35 @@ impl<'de, 'a: 'de> serde::Deserializer<'de> for Deserializer<'a> {
     where
         V: serde::de::Visitor<'de>,
     {
-        const SIZE: usize = std::mem::size_of::<u64>();
-        let value = unsafe { self.deserialize_integer::<u64, SIZE, V>(&visitor) }?;
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        let value = u64::from_le_bytes(self.deserialize_array(&visitor)?);
         visitor.visit_u64(value)
     }
 
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        let value = u128::from_le_bytes(self.deserialize_array(&visitor)?);
+        visitor.visit_u128(value)
+    }
+
     fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        const SIZE: usize = std::mem::size_of::<u32>();
-        let value = unsafe { self.deserialize_integer::<u32, SIZE, V>(&visitor) }?;
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        let value = u32::from_le_bytes(self.deserialize_array(&visitor)?);
         visitor.visit_f32(f32::from_bits(value))
     }
 
@@ -185,8 +627,11 @@ impl<'de, 'a: 'de> serde::Deserializer<'de> for Deserializer<'a> {
     where
         V: serde::de::Visitor<'de>,
     {
-        const SIZE: usize = std::mem::size_of::<u64>();
-        let value = unsafe { self.deserialize_integer::<u64, SIZE, V>(&visitor) }?;
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        let value = u64::from_le_bytes(self.deserialize_array(&visitor)?);
         visitor.visit_f64(f64::from_bits(value))
     }
 
@@ -194,12 +639,11 @@ impl<'de, 'a: 'de> serde::Deserializer<'de> for Deserializer<'a> {
     where
         V: serde::de::Visitor<'de>,
     {
-        const SIZE: usize = std::mem::size_of::<u32>();
-        let value = unsafe { self.deserialize_integer::<u32, SIZE, V>(&visitor) }?;
-        let value = char::from_u32(value).ok_or(<Error as de::Error>::invalid_value(
-            Unexpected::Unsigned(value as u64),
-            &visitor,
-        ))?;
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        let value = self.parse_char(&visitor)?;
         visitor.visit_char(value)
     }
 
@@ -207,39 +651,57 @@ impl<'de, 'a: 'de> serde::Deserializer<'de> for Deserializer<'a> {
     where
         V: serde::de::Visitor<'de>,
     {
-        visitor.visit_str(self.deserialize_str()?)
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        visitor.visit_borrowed_str(self.parse_str()?)
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        visitor.visit_string(self.deserialize_str()?.to_string())
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        visitor.visit_string(self.parse_str()?.to_string())
     }
 
     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        visitor.visit_bytes(self.buffer)
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        visitor.visit_borrowed_bytes(self.parse_bytes()?)
     }
 
     fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        visitor.visit_byte_buf(self.buffer.to_vec())
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        visitor.visit_byte_buf(self.parse_bytes()?.to_vec())
     }
 
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        match *self.buffer.get(0).ok_or(Error::EmptyBuffer)? {
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        match self.take(1)?[0] {
             0 => visitor.visit_none(),
-            1 => visitor.visit_some(Self {
-                buffer: &self.buffer[1..],
-            }),
+            1 => self.deeper(|deserializer| visitor.visit_some(deserializer)),
             n => Err(de::Error::invalid_value(
                 Unexpected::Unsigned(n as u64),
                 &visitor,
@@ -251,8 +713,8 @@ impl<'de, 'a: 'de> serde::Deserializer<'de> for Deserializer<'a> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if !self.buffer.is_empty() {
-            return Err(Error::WrongDeserializeType);
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
         }
 
         visitor.visit_unit()
@@ -271,83 +733,498 @@ impl<'de, 'a: 'de> serde::Deserializer<'de> for Deserializer<'a> {
 
     fn deserialize_newtype_struct<V>(
         self,
-        name: &'static str,
+        _name: &'static str,
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_newtype_struct(self)
     }
 
     fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        todo!()
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        let len = self.parse_len()?;
+        self.deeper(|deserializer| visitor.visit_seq(Access::new(deserializer, len)))
     }
 
     fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        todo!()
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        self.deeper(|deserializer| visitor.visit_seq(Access::new(deserializer, len)))
     }
 
     fn deserialize_tuple_struct<V>(
         self,
-        name: &'static str,
+        _name: &'static str,
         len: usize,
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        todo!()
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        self.expect_len(len, &visitor)?;
+        self.deserialize_tuple(len, visitor)
     }
 
     fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        todo!()
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        let len = self.parse_len()?;
+        self.deeper(|deserializer| visitor.visit_map(Access::new(deserializer, len)))
     }
 
     fn deserialize_struct<V>(
         self,
-        name: &'static str,
+        _name: &'static str,
         fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        todo!()
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        self.deeper(|deserializer| visitor.visit_seq(Access::with_fields(deserializer, fields)))
     }
 
     fn deserialize_enum<V>(
         self,
-        name: &'static str,
+        _name: &'static str,
         variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        todo!()
+        if !self.config.self_describing {
+            return visitor.visit_enum(Enum {
+                deserializer: self,
+                variants,
+                name: None,
+            });
+        }
+
+        // Unit variants are written as their name, others as a map from it to their content.
+        let name = match self.take(1)?[0] {
+            tag::STR => self.parse_str()?,
+            tag::MAP => {
+                self.expect_len(1, &visitor)?;
+                match self.take(1)?[0] {
+                    tag::STR => self.parse_str()?,
+                    tag => return Err(Error::UnknownTag(tag)),
+                }
+            }
+            tag => return Err(Error::UnknownTag(tag)),
+        };
+        visitor.visit_enum(Enum {
+            deserializer: self,
+            variants,
+            name: Some(name),
+        })
     }
 
+    /// Identifiers are encoded as indices, like enum variants.
     fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        todo!()
+        if self.config.self_describing {
+            return self.deserialize_any(visitor);
+        }
+
+        self.deserialize_u32(visitor)
     }
 
+    /// Without type information the extent of an unknown value can't be determined, so ignored
+    /// values, e.g. fields added by a newer version of a struct, can only be skipped in
+    /// self-describing mode. Otherwise this fails like `deserialize_any`.
     fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        todo!()
+        self.deserialize_any(visitor)
+    }
+}
+
+struct Access<'b, 'a> {
+    deserializer: &'b mut Deserializer<'a>,
+    len: usize,
+    index: usize,
+    fields: &'static [&'static str],
+    /// Entries run until an END tag rather than for `len` entries.
+    terminated: bool,
+    /// Key of the current map entry in self-describing mode, if it's a string such as a field name.
+    key: Option<&'a str>,
+}
+
+impl<'b, 'a> Access<'b, 'a> {
+    fn new(deserializer: &'b mut Deserializer<'a>, len: usize) -> Self {
+        Self {
+            deserializer,
+            len,
+            index: 0,
+            fields: &[],
+            terminated: false,
+            key: None,
+        }
+    }
+
+    fn terminated(deserializer: &'b mut Deserializer<'a>) -> Self {
+        Self {
+            terminated: true,
+            ..Self::new(deserializer, 0)
+        }
+    }
+
+    fn with_fields(
+        deserializer: &'b mut Deserializer<'a>,
+        fields: &'static [&'static str],
+    ) -> Self {
+        Self {
+            fields,
+            ..Self::new(deserializer, fields.len())
+        }
+    }
+
+    /// Moves to the next element, returning the path segment it's located at.
+    fn advance(&mut self) -> Option<Segment<'a>> {
+        if self.terminated {
+            if let Some(rest) = self.deserializer.buffer.strip_prefix(&[tag::END]) {
+                self.deserializer.buffer = rest;
+                return None;
+            }
+        } else if self.len == 0 {
+            return None;
+        } else {
+            self.len -= 1;
+        }
+        self.index += 1;
+
+        let index = self.index - 1;
+        Some(
+            self.fields
+                .get(index)
+                .map_or(Segment::Index(index), |&name| Segment::Field(name)),
+        )
+    }
+}
+
+impl<'de, 'a: 'de, 'b> SeqAccess<'de> for Access<'b, 'a> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        let Some(segment) = self.advance() else {
+            return Ok(None);
+        };
+
+        self.deserializer
+            .nested(segment, |deserializer| seed.deserialize(deserializer))
+            .map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        (!self.terminated).then_some(self.len)
+    }
+}
+
+impl<'de, 'a: 'de, 'b> MapAccess<'de> for Access<'b, 'a> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        let Some(segment) = self.advance() else {
+            return Ok(None);
+        };
+
+        self.key = None;
+        if self.deserializer.config.self_describing {
+            self.key = self.deserializer.peek_str();
+        }
+        let segment = self.key.map_or(segment, Segment::Field);
+        self.deserializer
+            .nested(segment, |deserializer| seed.deserialize(deserializer))
+            .map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let segment = self
+            .key
+            .map_or(Segment::Index(self.index.saturating_sub(1)), Segment::Field);
+        self.deserializer
+            .nested(segment, |deserializer| seed.deserialize(deserializer))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        (!self.terminated).then_some(self.len)
+    }
+}
+
+struct Enum<'b, 'a> {
+    deserializer: &'b mut Deserializer<'a>,
+    variants: &'static [&'static str],
+    /// Variants are written by index, or by name in self-describing mode.
+    name: Option<&'a str>,
+}
+
+impl<'de, 'a: 'de, 'b> EnumAccess<'de> for Enum<'b, 'a> {
+    type Error = Error;
+
+    type Variant = Variant<'b, 'a>;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let (value, variant_index) = match self.name {
+            Some(name) => (
+                seed.deserialize(name.into_deserializer())?,
+                self.variants.iter().position(|&variant| variant == name),
+            ),
+            None => {
+                let variant_index = u32::deserialize(&mut *self.deserializer)?;
+                (
+                    seed.deserialize(variant_index.into_deserializer())?,
+                    Some(variant_index as usize),
+                )
+            }
+        };
+        let name = variant_index
+            .and_then(|index| self.variants.get(index))
+            .copied()
+            .unwrap_or("?");
+        Ok((
+            value,
+            Variant {
+                deserializer: self.deserializer,
+                segment: Segment::Variant(name),
+            },
+        ))
+    }
+}
+
+struct Variant<'b, 'a> {
+    deserializer: &'b mut Deserializer<'a>,
+    segment: Segment<'a>,
+}
+
+impl<'de, 'a: 'de, 'b> VariantAccess<'de> for Variant<'b, 'a> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.deserializer.nested(self.segment, |deserializer| {
+            deserializer.deeper(|deserializer| seed.deserialize(deserializer))
+        })
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        self.deserializer.nested(self.segment, |deserializer| {
+            if deserializer.config.self_describing {
+                return serde::Deserializer::deserialize_tuple(deserializer, len, visitor);
+            }
+
+            deserializer.expect_len(len, &visitor)?;
+            deserializer.deeper(|deserializer| visitor.visit_seq(Access::new(deserializer, len)))
+        })
+    }
+
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        self.deserializer.nested(self.segment, |deserializer| {
+            if deserializer.config.self_describing {
+                return serde::Deserializer::deserialize_any(deserializer, visitor);
+            }
+
+            deserializer
+                .deeper(|deserializer| visitor.visit_seq(Access::with_fields(deserializer, fields)))
+        })
+    }
+}
+
+macro_rules! forward_to_cursor {
+    ($($method:ident($($arg:ident: $ty:ty),*))*) => {
+        $(
+            fn $method<V>(mut self, $($arg: $ty,)* visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: serde::de::Visitor<'de>,
+            {
+                let value = self
+                    .check_header()
+                    .and_then(|()| serde::Deserializer::$method(&mut self, $($arg,)* visitor))
+                    .and_then(|value| self.end().map(|()| value))
+                    .map_err(|err| self.locate(err))?;
+                Ok(value)
+            }
+        )*
+    };
+}
+
+impl<'de, 'a: 'de> serde::Deserializer<'de> for Deserializer<'a> {
+    type Error = Error;
+
+    forward_to_cursor! {
+        deserialize_any()
+        deserialize_bool()
+        deserialize_i8()
+        deserialize_i16()
+        deserialize_i32()
+        deserialize_i64()
+        deserialize_i128()
+        deserialize_u8()
+        deserialize_u16()
+        deserialize_u32()
+        deserialize_u64()
+        deserialize_u128()
+        deserialize_f32()
+        deserialize_f64()
+        deserialize_char()
+        deserialize_str()
+        deserialize_string()
+        deserialize_bytes()
+        deserialize_byte_buf()
+        deserialize_option()
+        deserialize_unit()
+        deserialize_unit_struct(name: &'static str)
+        deserialize_newtype_struct(name: &'static str)
+        deserialize_seq()
+        deserialize_tuple(len: usize)
+        deserialize_tuple_struct(name: &'static str, len: usize)
+        deserialize_map()
+        deserialize_struct(name: &'static str, fields: &'static [&'static str])
+        deserialize_enum(name: &'static str, variants: &'static [&'static str])
+        deserialize_identifier()
+        deserialize_ignored_any()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use serde::{de::IgnoredAny, Serialize};
+
+    use super::{Deserializer, Error};
+    use crate::byte_buffer::{from_bytes, serializer::Serializer, to_bytes, EncodingConfig};
+
+    #[test]
+    fn reads_sequences() {
+        let empty: Vec<u32> = vec![];
+        assert_eq!(from_bytes(&to_bytes(&empty).unwrap()), Ok(empty));
+
+        let nested = vec![vec![1u16, 2], vec![], vec![u16::MAX]];
+        assert_eq!(from_bytes(&to_bytes(&nested).unwrap()), Ok(nested));
+
+        let strings = vec!["a".to_owned(), String::new(), "ü".to_owned()];
+        assert_eq!(from_bytes(&to_bytes(&strings).unwrap()), Ok(strings));
+
+        let deque: VecDeque<i8> = [-1, 0, 1].into();
+        assert_eq!(from_bytes(&to_bytes(&deque).unwrap()), Ok(deque));
+    }
+
+    #[test]
+    fn reads_values_back_to_back() {
+        let mut serializer = Serializer::with_writer(vec![], EncodingConfig::default()).unwrap();
+        serde::Serialize::serialize(&vec![1u8, 2], &mut serializer).unwrap();
+        serde::Serialize::serialize("text", &mut serializer).unwrap();
+        serde::Serialize::serialize(&7u32, &mut serializer).unwrap();
+        let buffer = serializer.into_inner();
+
+        let mut deserializer = Deserializer::new(&buffer);
+        assert_eq!(deserializer.read(), Ok(vec![1u8, 2]));
+        assert_eq!(deserializer.read(), Ok("text"));
+        assert_eq!(deserializer.read(), Ok(7u32));
+        assert_eq!(deserializer.position(), buffer.len());
+        assert_eq!(deserializer.end(), Ok(()));
+    }
+
+    #[test]
+    fn keeps_reporting_an_invalid_header() {
+        let mut deserializer = Deserializer::new(b"XXXXXXabc");
+        for _ in 0..2 {
+            let err = deserializer.read::<u8>().unwrap_err();
+            assert_eq!(err.kind(), &Error::MissingHeader);
+        }
+        assert_eq!(deserializer.end().unwrap_err(), Error::MissingHeader);
+        assert_eq!(deserializer.position(), 0);
+    }
+
+    #[test]
+    fn ignores_values_only_when_self_describing() {
+        let value = (1u8, "skipped", 2u8);
+        let err = from_bytes::<(u8, IgnoredAny, u8)>(&to_bytes(&value).unwrap()).unwrap_err();
+        assert_eq!(err.kind(), &Error::DeserializeAny);
+        assert!(
+            matches!(&err, Error::Located { path, .. } if path == "[1]"),
+            "{err:?}"
+        );
+
+        let config = EncodingConfig {
+            self_describing: true,
+            ..EncodingConfig::default()
+        };
+        let buffer = value.serialize(Serializer::new(config)).unwrap();
+        assert!(matches!(
+            from_bytes::<(u8, IgnoredAny, u8)>(&buffer),
+            Ok((1, IgnoredAny, 2))
+        ));
+    }
+
+    #[test]
+    fn rejects_lengths_past_the_buffer() {
+        let mut buffer = to_bytes(&vec![1u8]).unwrap();
+        let len = buffer.len();
+        buffer[len - 9..len - 1].copy_from_slice(&u64::MAX.to_le_bytes());
+        // The element after the last one in the buffer fails, before any large allocation.
+        let err = from_bytes::<Vec<u8>>(&buffer).unwrap_err();
+        assert!(
+            matches!(&err, Error::Located { offset, .. } if *offset == buffer.len()),
+            "{err:?}"
+        );
     }
 }
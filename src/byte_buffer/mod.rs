@@ -1,4 +1,409 @@
 pub mod deserializer;
 pub mod serializer;
+pub mod stream;
+
+use std::io::{BufWriter, Read, Write};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use self::{deserializer::Deserializer, serializer::Serializer};
 
 const EOT: u8 = 3;
+
+/// Written before the first value, followed by [`VERSION`] and the [`flag`]s of the encoding,
+/// unless [`EncodingConfig::raw`](field@EncodingConfig::raw) is set.
+const MAGIC: &[u8] = b"RSTG";
+
+/// Version of the format, bumped whenever the encoding of existing data changes.
+const VERSION: u8 = 1;
+
+const HEADER_LEN: usize = MAGIC.len() + 2;
+
+/// Bits of the header byte recording the [`EncodingConfig`] a buffer was serialized with.
+mod flag {
+    pub const CANONICAL_NAN: u8 = 1;
+    pub const LENGTH_PREFIXED_STRINGS: u8 = 2;
+    pub const VARINT_LENGTHS: u8 = 4;
+    pub const SELF_DESCRIBING: u8 = 8;
+    pub const ALL: u8 = CANONICAL_NAN | LENGTH_PREFIXED_STRINGS | VARINT_LENGTHS | SELF_DESCRIBING;
+}
+
+/// Type tags written before every value when [`EncodingConfig::self_describing`] is set.
+mod tag {
+    pub const UNIT: u8 = 0;
+    pub const BOOL: u8 = 1;
+    pub const I8: u8 = 2;
+    pub const I16: u8 = 3;
+    pub const I32: u8 = 4;
+    pub const I64: u8 = 5;
+    pub const I128: u8 = 6;
+    pub const U8: u8 = 7;
+    pub const U16: u8 = 8;
+    pub const U32: u8 = 9;
+    pub const U64: u8 = 10;
+    pub const U128: u8 = 11;
+    pub const F32: u8 = 12;
+    pub const F64: u8 = 13;
+    pub const CHAR: u8 = 14;
+    pub const STR: u8 = 15;
+    pub const BYTES: u8 = 16;
+    pub const NONE: u8 = 17;
+    pub const SOME: u8 = 18;
+    pub const SEQ: u8 = 19;
+    pub const MAP: u8 = 20;
+    /// Sequences and maps of unknown length, whose entries run until an [`END`] tag.
+    pub const UNSIZED_SEQ: u8 = 21;
+    pub const UNSIZED_MAP: u8 = 22;
+    pub const END: u8 = 23;
+}
+
+/// Serializes `value` into a new buffer with the default encoding.
+pub fn to_bytes<T>(value: &T) -> Result<Vec<u8>, serializer::Error>
+where
+    T: ?Sized + Serialize,
+{
+    value.serialize(Serializer::default())
+}
+
+/// Deserializes a value from the whole of `buffer`, borrowing strings and bytes from it.
+pub fn from_bytes<'a, T>(buffer: &'a [u8]) -> Result<T, deserializer::Error>
+where
+    T: Deserialize<'a>,
+{
+    T::deserialize(Deserializer::new(buffer))
+}
+
+/// Serializes `value` straight into `writer`, without building the whole buffer in memory.
+///
+/// Writes are buffered, so an unbuffered sink such as a file or socket needs no wrapper of its
+/// own. The buffer is flushed before returning.
+pub fn to_writer<T, W>(writer: W, value: &T) -> Result<(), serializer::Error>
+where
+    T: ?Sized + Serialize,
+    W: Write,
+{
+    let mut serializer =
+        Serializer::with_writer(BufWriter::new(writer), EncodingConfig::default())?;
+    value.serialize(&mut serializer)?;
+    serializer
+        .into_inner()
+        .flush()
+        .map_err(|err| serializer::Error::Io(err.to_string()))
+}
+
+/// Deserializes a value from everything `reader` yields.
+///
+/// Unlike [`to_writer`], this buffers: the whole of `reader` is read into memory before decoding,
+/// since the format carries no overall length and the deserializer decodes from a byte slice.
+/// Any bytes left over once the value is complete are an error.
+pub fn from_reader<T, R>(mut reader: R) -> Result<T, deserializer::Error>
+where
+    T: DeserializeOwned,
+    R: Read,
+{
+    let mut buffer = vec![];
+    reader
+        .read_to_end(&mut buffer)
+        .map_err(|err| deserializer::Error::Io(err.to_string()))?;
+    from_bytes(&buffer)
+}
+
+/// Encoding options of the byte buffer format.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EncodingConfig {
+    /// Floats are written as their IEEE 754 bit pattern, so negative zero and NaN payloads are
+    /// preserved exactly. When set, every NaN is written as the canonical quiet NaN instead, so
+    /// equal values always produce identical bytes regardless of platform or Rust version.
+    pub canonical_nan: bool,
+    pub strings: StringEncoding,
+    pub lengths: LengthEncoding,
+    /// Leaves out the magic bytes and version otherwise written before the first value, for
+    /// buffers whose format is known from elsewhere, e.g. values nested in another buffer.
+    pub raw: bool,
+    /// Writes a type tag before every value and names struct fields and enum variants, so the
+    /// buffer can be read without knowing its type: `deserialize_any`, untagged enums,
+    /// `#[serde(flatten)]` and dynamic values such as `serde_json::Value` all work, at the cost
+    /// of a larger buffer.
+    pub self_describing: bool,
+}
+
+impl EncodingConfig {
+    /// The default encoding without a header.
+    pub(crate) fn raw() -> Self {
+        Self {
+            raw: true,
+            ..Self::default()
+        }
+    }
+
+    /// The format header of buffers serialized with this configuration.
+    fn header(&self) -> Vec<u8> {
+        let flags = [
+            (self.canonical_nan, flag::CANONICAL_NAN),
+            (
+                self.strings == StringEncoding::LengthPrefixed,
+                flag::LENGTH_PREFIXED_STRINGS,
+            ),
+            (self.lengths == LengthEncoding::Varint, flag::VARINT_LENGTHS),
+            (self.self_describing, flag::SELF_DESCRIBING),
+        ]
+        .into_iter()
+        .filter(|&(set, _)| set)
+        .fold(0, |flags, (_, flag)| flags | flag);
+        [MAGIC, &[VERSION, flags]].concat()
+    }
+
+    /// The configuration recorded by header `flags`, or None if they include unknown bits.
+    fn with_flags(self, flags: u8) -> Option<Self> {
+        if flags & !flag::ALL != 0 {
+            return None;
+        }
+
+        Some(Self {
+            canonical_nan: flags & flag::CANONICAL_NAN != 0,
+            strings: if flags & flag::LENGTH_PREFIXED_STRINGS != 0 {
+                StringEncoding::LengthPrefixed
+            } else {
+                StringEncoding::Terminated
+            },
+            lengths: if flags & flag::VARINT_LENGTHS != 0 {
+                LengthEncoding::Varint
+            } else {
+                LengthEncoding::Fixed
+            },
+            self_describing: flags & flag::SELF_DESCRIBING != 0,
+            ..self
+        })
+    }
+}
+
+/// How strings are delimited in the buffer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StringEncoding {
+    /// The string bytes followed by an EOT byte, which therefore can't appear in the string.
+    #[default]
+    Terminated,
+    /// A u64 byte length followed by the string bytes, allowing any UTF-8 content.
+    LengthPrefixed,
+}
+
+/// How lengths of sequences, maps and length prefixed strings are written.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LengthEncoding {
+    /// A little-endian u64.
+    #[default]
+    Fixed,
+    /// An unsigned LEB128 varint, a single byte for lengths below 128.
+    Varint,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::{
+        deserializer::{self, Deserializer},
+        serializer::Serializer,
+        EncodingConfig, LengthEncoding, StringEncoding,
+    };
+    use crate::message::Localize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+        Empty,
+        Circle(f32),
+        Line(i16, i16),
+        Rect { width: u32, height: u32 },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Sample {
+        name: String,
+        #[serde(with = "serde_bytes_compat")]
+        data: Vec<u8>,
+        flag: bool,
+        letter: char,
+        ratio: f64,
+        wide: (i128, u128),
+        shapes: Vec<Shape>,
+        counts: BTreeMap<String, u64>,
+        parent: Option<Box<Sample>>,
+        missing: Option<u8>,
+        unit: (),
+    }
+
+    /// Serializes the Vec as bytes rather than a sequence of u8, without the serde_bytes crate.
+    mod serde_bytes_compat {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(bytes)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Vec<u8>, D::Error> {
+            <&[u8]>::deserialize(deserializer).map(<[u8]>::to_vec)
+        }
+    }
+
+    fn sample() -> Sample {
+        let leaf = Sample {
+            name: "leaf".into(),
+            data: vec![],
+            flag: false,
+            letter: 'é',
+            ratio: -0.0,
+            wide: (i128::MIN, u128::MAX),
+            shapes: vec![],
+            counts: BTreeMap::new(),
+            parent: None,
+            missing: None,
+            unit: (),
+        };
+        Sample {
+            name: "root ✓".into(),
+            data: vec![0, 3, 255],
+            flag: true,
+            letter: 'x',
+            ratio: 1.5e300,
+            wide: (-1, 1),
+            shapes: vec![
+                Shape::Empty,
+                Shape::Circle(2.5),
+                Shape::Line(i16::MIN, i16::MAX),
+                Shape::Rect {
+                    width: 3,
+                    height: 4,
+                },
+            ],
+            counts: [("a".into(), 1), ("long key".repeat(20), u64::MAX)].into(),
+            parent: Some(Box::new(leaf)),
+            missing: None,
+            unit: (),
+        }
+    }
+
+    fn configs() -> impl Iterator<Item = EncodingConfig> {
+        (0..32).map(|bits: u8| EncodingConfig {
+            canonical_nan: bits & 1 != 0,
+            strings: if bits & 2 != 0 {
+                StringEncoding::LengthPrefixed
+            } else {
+                StringEncoding::Terminated
+            },
+            lengths: if bits & 4 != 0 {
+                LengthEncoding::Varint
+            } else {
+                LengthEncoding::Fixed
+            },
+            raw: bits & 8 != 0,
+            self_describing: bits & 16 != 0,
+        })
+    }
+
+    fn serialize<T: Serialize>(value: &T, config: EncodingConfig) -> Vec<u8> {
+        value.serialize(Serializer::new(config)).unwrap()
+    }
+
+    fn deserialize<'a, T: Deserialize<'a>>(
+        buffer: &'a [u8],
+        config: EncodingConfig,
+    ) -> Result<T, deserializer::Error> {
+        T::deserialize(Deserializer::with_config(buffer, config))
+    }
+
+    #[test]
+    fn round_trips_every_config() {
+        for config in configs() {
+            let buffer = serialize(&sample(), config);
+            assert_eq!(deserialize(&buffer, config), Ok(sample()), "{config:?}");
+        }
+    }
+
+    #[test]
+    fn reads_config_from_header() {
+        for config in configs().filter(|config| !config.raw) {
+            let buffer = serialize(&sample(), config);
+            assert_eq!(super::from_bytes(&buffer), Ok(sample()), "{config:?}");
+        }
+    }
+
+    #[test]
+    fn writer_matches_bytes() {
+        let mut buffer = vec![];
+        super::to_writer(&mut buffer, &sample()).unwrap();
+        assert_eq!(buffer, super::to_bytes(&sample()).unwrap());
+    }
+
+    #[test]
+    fn self_describing_reads_untagged_enums() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(untagged)]
+        enum Reading {
+            Number(i64),
+            Text(String),
+            Pair { key: String, value: Option<f64> },
+        }
+
+        let readings = vec![
+            Reading::Number(-7),
+            Reading::Text("n/a".into()),
+            Reading::Pair {
+                key: "t".into(),
+                value: Some(21.5),
+            },
+        ];
+        for config in configs().filter(|config| config.self_describing) {
+            let buffer = serialize(&readings, config);
+            assert_eq!(
+                deserialize(&buffer, config).as_ref(),
+                Ok(&readings),
+                "{config:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        for config in configs() {
+            let buffer = serialize(&sample(), config);
+            for len in 0..buffer.len() {
+                assert!(
+                    deserialize::<Sample>(&buffer[..len], config).is_err(),
+                    "{config:?} truncated to {len} bytes"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_trailing_bytes() {
+        let mut buffer = super::to_bytes(&sample()).unwrap();
+        buffer.push(0);
+        assert_eq!(
+            super::from_bytes::<Sample>(&buffer).map_err(|err| err.code()),
+            Err("wrong_deserialize_type")
+        );
+    }
+
+    #[test]
+    fn limits_nesting_depth() {
+        type Nested = Vec<Vec<Vec<Vec<Vec<Vec<u8>>>>>>;
+        let nested: Nested = vec![vec![vec![vec![vec![vec![1]]]]]];
+        for config in configs().filter(|config| !config.raw) {
+            let buffer = serialize(&nested, config);
+            let deserializer = Deserializer::new(&buffer).max_depth(6);
+            assert_eq!(Nested::deserialize(deserializer), Ok(nested.clone()));
+
+            let deserializer = Deserializer::new(&buffer).max_depth(5);
+            assert_eq!(
+                Nested::deserialize(deserializer).map_err(|err| err.code()),
+                Err("depth_limit_exceeded"),
+                "{config:?}"
+            );
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::fmt::Display;
+use std::{fmt::Display, io::Write};
 
 use serde::{
     ser::{
@@ -8,18 +8,49 @@ use serde::{
     Serialize,
 };
 
-use super::EOT;
+use crate::{
+    message::{self, English, Localize, MessageCatalog},
+    varint,
+};
+
+use super::{tag, EncodingConfig, LengthEncoding, StringEncoding, EOT};
+
+const CANONICAL_NAN_F32: u32 = 0x7fc0_0000;
+const CANONICAL_NAN_F64: u64 = 0x7ff8_0000_0000_0000;
 
 #[derive(Debug, PartialEq)]
 pub enum Error {
     Custom(String),
     UnsizedSeq,
     UnsizedMap,
+    EotInStr,
+    Io(String),
+}
+
+impl Localize for Error {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::Custom(_) => "custom",
+            Self::UnsizedSeq => "unsized_seq",
+            Self::UnsizedMap => "unsized_map",
+            Self::EotInStr => "eot_in_str",
+            Self::Io(_) => "io",
+        }
+    }
+
+    fn localize(&self, catalog: &dyn MessageCatalog) -> String {
+        match self {
+            Self::Custom(arg) | Self::Io(arg) => {
+                message::localize(catalog, self.code(), std::slice::from_ref(arg))
+            }
+            _ => message::localize(catalog, self.code(), &[]),
+        }
+    }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        f.write_str(&self.localize(&English))
     }
 }
 
@@ -34,24 +65,139 @@ impl serde::ser::Error for Error {
     }
 }
 
-#[derive(Default)]
-pub struct Serializer {
-    buffer: Vec<u8>,
+/// Writes every value into a single sink, a growable buffer by default.
+///
+/// The format header is written once, when the Serializer is created. `&mut Serializer` then
+/// appends each value to the sink, while the owned Serializer serializes a single value and
+/// returns the bytes written.
+pub struct Serializer<W = Vec<u8>> {
+    writer: W,
+    config: EncodingConfig,
+    /// Whether each sequence or map still open in self-describing mode ends with an END tag.
+    terminated: Vec<bool>,
 }
 
 impl Serializer {
-    fn serialize_single_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Error>
+    pub fn new(config: EncodingConfig) -> Self {
+        let writer = if config.raw { vec![] } else { config.header() };
+        Self {
+            writer,
+            config,
+            terminated: vec![],
+        }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.writer
+    }
+}
+
+impl Default for Serializer {
+    fn default() -> Self {
+        Self::new(EncodingConfig::default())
+    }
+}
+
+impl<W: Write> Serializer<W> {
+    /// Writes the values straight into `writer` instead of buffering them, failing if the
+    /// header can't be written. Every primitive is a separate write, so wrap unbuffered sinks
+    /// in a [`BufWriter`](std::io::BufWriter).
+    pub fn with_writer(writer: W, config: EncodingConfig) -> Result<Self, Error> {
+        let mut serializer = Self {
+            writer,
+            config,
+            terminated: vec![],
+        };
+        if !config.raw {
+            serializer.write(&config.header())?;
+        }
+        Ok(serializer)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.writer
+            .write_all(bytes)
+            .map_err(|err| Error::Io(err.to_string()))
+    }
+
+    fn write_len(&mut self, len: usize) -> Result<(), Error> {
+        match self.config.lengths {
+            LengthEncoding::Fixed => self.write(&(len as u64).to_le_bytes()),
+            LengthEncoding::Varint => {
+                self.write(varint::encode(len as u64, &mut [0; varint::MAX_LEN]))
+            }
+        }
+    }
+
+    /// Starts a sequence or map. Their length is required, except in self-describing mode where
+    /// unknown ones are terminated instead, e.g. the maps of `#[serde(flatten)]` structs.
+    fn open(
+        &mut self,
+        len: Option<usize>,
+        sized_tag: u8,
+        unsized_tag: u8,
+        err: Error,
+    ) -> Result<(), Error> {
+        if self.config.self_describing {
+            self.terminated.push(len.is_none());
+            if len.is_none() {
+                return self.write_tag(unsized_tag);
+            }
+        }
+
+        let len = len.ok_or(err)?;
+        self.write_tag(sized_tag)?;
+        self.write_len(len)
+    }
+
+    /// Ends a sequence or map started by [`Serializer::open`].
+    fn close(&mut self) -> Result<(), Error> {
+        match self.terminated.pop() {
+            Some(true) => self.write_tag(tag::END),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes the type tag of the next value in self-describing mode.
+    fn write_tag(&mut self, tag: u8) -> Result<(), Error> {
+        if !self.config.self_describing {
+            return Ok(());
+        }
+
+        self.write(&[tag])
+    }
+
+    /// Variants are written by index, or in self-describing mode as a single entry map from the
+    /// variant name to its content.
+    fn write_variant(&mut self, variant_index: u32, variant: &'static str) -> Result<(), Error> {
+        if !self.config.self_describing {
+            return self.write(&variant_index.to_le_bytes());
+        }
+
+        self.write_tag(tag::MAP)?;
+        self.write_len(1)?;
+        serde::Serializer::serialize_str(self, variant)
+    }
+
+    /// Struct fields are identified by their position, or in self-describing mode by their name.
+    fn write_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        Ok(self
-            .buffer
-            .extend_from_slice(value.serialize(Self::default())?.as_slice()))
+        if self.config.self_describing {
+            key.serialize(&mut *self)?;
+        }
+
+        value.serialize(self)
     }
 }
 
-impl serde::Serializer for Serializer {
-    type Ok = Vec<u8>;
+impl<W: Write> serde::Serializer for &mut Serializer<W> {
+    type Ok = ();
 
     type Error = Error;
 
@@ -70,76 +216,135 @@ impl serde::Serializer for Serializer {
     type SerializeStructVariant = Self;
 
     fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
-        Ok(vec![if v { 1 } else { 0 }])
+        self.write_tag(tag::BOOL)?;
+        self.write(&[if v { 1 } else { 0 }])
     }
 
     fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
-        (v as u8).serialize(self)
+        self.write_tag(tag::I8)?;
+        self.write(&v.to_le_bytes())
     }
 
     fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
-        (v as u16).serialize(self)
+        self.write_tag(tag::I16)?;
+        self.write(&v.to_le_bytes())
     }
 
     fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
-        (v as u32).serialize(self)
+        self.write_tag(tag::I32)?;
+        self.write(&v.to_le_bytes())
     }
 
     fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
-        (v as u64).serialize(self)
+        self.write_tag(tag::I64)?;
+        self.write(&v.to_le_bytes())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
+        self.write_tag(tag::I128)?;
+        self.write(&v.to_le_bytes())
     }
 
     fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
-        Ok(vec![v])
+        self.write_tag(tag::U8)?;
+        self.write(&[v])
     }
 
     fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
-        Ok(unsafe { std::mem::transmute::<_, [u8; 2]>(v) }.to_vec())
+        self.write_tag(tag::U16)?;
+        self.write(&v.to_le_bytes())
     }
 
     fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
-        Ok(unsafe { std::mem::transmute::<_, [u8; 4]>(v) }.to_vec())
+        self.write_tag(tag::U32)?;
+        self.write(&v.to_le_bytes())
     }
 
     fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
-        Ok(unsafe { std::mem::transmute::<_, [u8; 8]>(v) }.to_vec())
+        self.write_tag(tag::U64)?;
+        self.write(&v.to_le_bytes())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
+        self.write_tag(tag::U128)?;
+        self.write(&v.to_le_bytes())
     }
 
     fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
-        v.to_bits().serialize(self)
+        let bits = if self.config.canonical_nan && v.is_nan() {
+            CANONICAL_NAN_F32
+        } else {
+            v.to_bits()
+        };
+
+        self.write_tag(tag::F32)?;
+        self.write(&bits.to_le_bytes())
     }
 
     fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
-        v.to_bits().serialize(self)
+        let bits = if self.config.canonical_nan && v.is_nan() {
+            CANONICAL_NAN_F64
+        } else {
+            v.to_bits()
+        };
+
+        self.write_tag(tag::F64)?;
+        self.write(&bits.to_le_bytes())
     }
 
     fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
-        (v as u32).serialize(self)
+        self.write_tag(tag::CHAR)?;
+        self.write(&(v as u32).to_le_bytes())
     }
 
     fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
-        [v.as_bytes(), &[EOT]].concat().serialize(self)
+        self.write_tag(tag::STR)?;
+        match self.config.strings {
+            StringEncoding::Terminated => {
+                if v.as_bytes().contains(&EOT) {
+                    return Err(Error::EotInStr);
+                }
+
+                self.write(v.as_bytes())?;
+                self.write(&[EOT])?;
+            }
+            StringEncoding::LengthPrefixed => {
+                self.write_len(v.len())?;
+                self.write(v.as_bytes())?;
+            }
+        }
+        Ok(())
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
-        Ok(v.to_vec())
+        self.write_tag(tag::BYTES)?;
+        self.write_len(v.len())?;
+        self.write(v)
     }
 
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
-        false.serialize(self)
+        if self.config.self_describing {
+            return self.write_tag(tag::NONE);
+        }
+
+        self.write(&[0])
     }
 
-    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
-        let mut output = true.serialize(Self::default())?;
-        output.extend(value.serialize(self)?);
-        Ok(output)
+        if self.config.self_describing {
+            self.write_tag(tag::SOME)?;
+        } else {
+            self.write(&[1])?;
+        }
+
+        value.serialize(self)
     }
 
     fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
-        Ok(vec![])
+        self.write_tag(tag::UNIT)
     }
 
     fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
@@ -150,48 +355,52 @@ impl serde::Serializer for Serializer {
         self,
         _name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
     ) -> Result<Self::Ok, Self::Error> {
-        variant_index.serialize(self)
+        if self.config.self_describing {
+            return self.serialize_str(variant);
+        }
+
+        self.write(&variant_index.to_le_bytes())
     }
 
-    fn serialize_newtype_struct<T: ?Sized>(
+    fn serialize_newtype_struct<T>(
         self,
         _name: &'static str,
         value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
         value.serialize(self)
     }
 
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T>(
         self,
         _name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
-        let mut output = variant_index.serialize(Self::default())?;
-        output.extend(value.serialize(self)?);
-        Ok(output)
+        self.write_variant(variant_index, variant)?;
+        value.serialize(self)
     }
 
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
-        match len {
-            None => Err(Error::UnsizedSeq),
-            Some(len) => Ok(Self {
-                buffer: len.serialize(self)?,
-            }),
-        }
+        self.open(len, tag::SEQ, tag::UNSIZED_SEQ, Error::UnsizedSeq)?;
+        Ok(self)
     }
 
-    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
-        Ok(Self::default())
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        if self.config.self_describing {
+            self.write_tag(tag::SEQ)?;
+            self.write_len(len)?;
+        }
+
+        Ok(self)
     }
 
     fn serialize_tuple_struct(
@@ -199,51 +408,301 @@ impl serde::Serializer for Serializer {
         _name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleStruct, Self::Error> {
-        Ok(Self {
-            buffer: len.serialize(self)?,
-        })
+        self.write_tag(tag::SEQ)?;
+        self.write_len(len)?;
+        Ok(self)
     }
 
     fn serialize_tuple_variant(
         self,
         _name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
-        let mut buffer = variant_index.serialize(self)?;
-        buffer.extend(len.serialize(Self::default())?);
-
-        Ok(Self { buffer })
+        self.write_variant(variant_index, variant)?;
+        self.write_tag(tag::SEQ)?;
+        self.write_len(len)?;
+        Ok(self)
     }
 
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
-        match len {
-            None => Err(Error::UnsizedMap),
-            Some(len) => Ok(Self {
-                buffer: len.serialize(self)?,
-            }),
-        }
+        self.open(len, tag::MAP, tag::UNSIZED_MAP, Error::UnsizedMap)?;
+        Ok(self)
     }
 
     fn serialize_struct(
         self,
         _name: &'static str,
-        _len: usize,
+        len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        Ok(Self::default())
+        if self.config.self_describing {
+            self.write_tag(tag::MAP)?;
+            self.write_len(len)?;
+        }
+
+        Ok(self)
     }
 
     fn serialize_struct_variant(
         self,
         _name: &'static str,
         variant_index: u32,
-        _variant: &'static str,
-        _len: usize,
+        variant: &'static str,
+        len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
-        Ok(Self {
-            buffer: variant_index.serialize(self)?,
-        })
+        self.write_variant(variant_index, variant)?;
+        if self.config.self_describing {
+            self.write_tag(tag::MAP)?;
+            self.write_len(len)?;
+        }
+
+        Ok(self)
+    }
+}
+
+impl<W: Write> SerializeSeq for &mut Serializer<W> {
+    type Ok = ();
+
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.close()
+    }
+}
+
+impl<W: Write> SerializeTuple for &mut Serializer<W> {
+    type Ok = ();
+
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(())
+    }
+}
+
+impl<W: Write> SerializeTupleStruct for &mut Serializer<W> {
+    type Ok = ();
+
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(())
+    }
+}
+
+impl<W: Write> SerializeTupleVariant for &mut Serializer<W> {
+    type Ok = ();
+
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(())
+    }
+}
+
+impl<W: Write> SerializeMap for &mut Serializer<W> {
+    type Ok = ();
+
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.close()
+    }
+}
+
+impl<W: Write> SerializeStruct for &mut Serializer<W> {
+    type Ok = ();
+
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.write_field(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(())
+    }
+}
+
+impl<W: Write> SerializeStructVariant for &mut Serializer<W> {
+    type Ok = ();
+
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.write_field(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(())
+    }
+}
+
+macro_rules! forward_to_buffer {
+    ($($method:ident($($arg:ident: $ty:ty),*))*) => {
+        $(
+            fn $method(mut self, $($arg: $ty),*) -> Result<Self::Ok, Self::Error> {
+                serde::Serializer::$method(&mut self, $($arg),*)?;
+                Ok(self.writer)
+            }
+        )*
+    };
+}
+
+macro_rules! forward_to_compound {
+    ($($method:ident($($arg:ident: $ty:ty),*) -> $compound:ident)*) => {
+        $(
+            fn $method(mut self, $($arg: $ty),*) -> Result<Self::$compound, Self::Error> {
+                serde::Serializer::$method(&mut self, $($arg),*)?;
+                Ok(self)
+            }
+        )*
+    };
+}
+
+impl serde::Serializer for Serializer {
+    type Ok = Vec<u8>;
+
+    type Error = Error;
+
+    type SerializeSeq = Self;
+
+    type SerializeTuple = Self;
+
+    type SerializeTupleStruct = Self;
+
+    type SerializeTupleVariant = Self;
+
+    type SerializeMap = Self;
+
+    type SerializeStruct = Self;
+
+    type SerializeStructVariant = Self;
+
+    forward_to_buffer! {
+        serialize_bool(v: bool)
+        serialize_i8(v: i8)
+        serialize_i16(v: i16)
+        serialize_i32(v: i32)
+        serialize_i64(v: i64)
+        serialize_i128(v: i128)
+        serialize_u8(v: u8)
+        serialize_u16(v: u16)
+        serialize_u32(v: u32)
+        serialize_u64(v: u64)
+        serialize_u128(v: u128)
+        serialize_f32(v: f32)
+        serialize_f64(v: f64)
+        serialize_char(v: char)
+        serialize_str(v: &str)
+        serialize_bytes(v: &[u8])
+        serialize_none()
+        serialize_unit()
+        serialize_unit_struct(name: &'static str)
+        serialize_unit_variant(name: &'static str, variant_index: u32, variant: &'static str)
+    }
+
+    forward_to_compound! {
+        serialize_seq(len: Option<usize>) -> SerializeSeq
+        serialize_tuple(len: usize) -> SerializeTuple
+        serialize_tuple_struct(name: &'static str, len: usize) -> SerializeTupleStruct
+        serialize_tuple_variant(
+            name: &'static str,
+            variant_index: u32,
+            variant: &'static str,
+            len: usize
+        ) -> SerializeTupleVariant
+        serialize_map(len: Option<usize>) -> SerializeMap
+        serialize_struct(name: &'static str, len: usize) -> SerializeStruct
+        serialize_struct_variant(
+            name: &'static str,
+            variant_index: u32,
+            variant: &'static str,
+            len: usize
+        ) -> SerializeStructVariant
+    }
+
+    fn serialize_some<T>(mut self, value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + serde::Serialize,
+    {
+        (&mut self).serialize_some(value)?;
+        Ok(self.writer)
+    }
+
+    fn serialize_newtype_struct<T>(
+        mut self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + serde::Serialize,
+    {
+        (&mut self).serialize_newtype_struct(name, value)?;
+        Ok(self.writer)
+    }
+
+    fn serialize_newtype_variant<T>(
+        mut self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + serde::Serialize,
+    {
+        (&mut self).serialize_newtype_variant(name, variant_index, variant, value)?;
+        Ok(self.writer)
     }
 }
 
@@ -252,15 +711,16 @@ impl SerializeSeq for Serializer {
 
     type Error = Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.serialize_single_element(value)
+        value.serialize(self)
     }
 
-    fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(self.buffer)
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        self.close()?;
+        Ok(self.writer)
     }
 }
 
@@ -269,15 +729,15 @@ impl SerializeTuple for Serializer {
 
     type Error = Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.serialize_single_element(value)
+        value.serialize(self)
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(self.buffer)
+        Ok(self.writer)
     }
 }
 
@@ -286,15 +746,15 @@ impl SerializeTupleStruct for Serializer {
 
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.serialize_single_element(value)
+        value.serialize(self)
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(self.buffer)
+        Ok(self.writer)
     }
 }
 
@@ -303,15 +763,15 @@ impl SerializeTupleVariant for Serializer {
 
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.serialize_single_element(value)
+        value.serialize(self)
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(self.buffer)
+        Ok(self.writer)
     }
 }
 
@@ -320,22 +780,23 @@ impl SerializeMap for Serializer {
 
     type Error = Error;
 
-    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<(), Self::Error>
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.serialize_single_element(key)
+        key.serialize(self)
     }
 
-    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.serialize_single_element(value)
+        value.serialize(self)
     }
 
-    fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(self.buffer)
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        self.close()?;
+        Ok(self.writer)
     }
 }
 
@@ -344,19 +805,15 @@ impl SerializeStruct for Serializer {
 
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(
-        &mut self,
-        _key: &'static str,
-        value: &T,
-    ) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.serialize_single_element(value)
+        self.write_field(key, value)
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(self.buffer)
+        Ok(self.writer)
     }
 }
 
@@ -365,18 +822,100 @@ impl SerializeStructVariant for Serializer {
 
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(
-        &mut self,
-        _key: &'static str,
-        value: &T,
-    ) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.serialize_single_element(value)
+        self.write_field(key, value)
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(self.buffer)
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use super::{Error, Serializer, CANONICAL_NAN_F32, CANONICAL_NAN_F64};
+    use crate::byte_buffer::{
+        deserializer::Deserializer, from_bytes, to_bytes, EncodingConfig, LengthEncoding,
+        StringEncoding, EOT,
+    };
+
+    fn serialize<T: Serialize>(value: &T, canonical_nan: bool) -> Vec<u8> {
+        let config = EncodingConfig {
+            canonical_nan,
+            raw: true,
+            ..EncodingConfig::default()
+        };
+        value.serialize(Serializer::new(config)).unwrap()
+    }
+
+    fn deserialize<'a, T: serde::Deserialize<'a>>(buffer: &'a [u8]) -> T {
+        T::deserialize(Deserializer::with_config(buffer, EncodingConfig::raw())).unwrap()
+    }
+
+    #[test]
+    fn canonicalizes_nan() {
+        let quiet_f32 = f32::from_bits(0x7fc0_0001);
+        let negative_f32 = f32::from_bits(0xffa0_0000);
+        assert_eq!(serialize(&quiet_f32, true), serialize(&negative_f32, true));
+        assert_eq!(serialize(&quiet_f32, true), CANONICAL_NAN_F32.to_le_bytes());
+
+        let quiet_f64 = f64::from_bits(0x7ff8_0000_0000_0001);
+        let signaling_f64 = f64::from_bits(0xfff0_0000_0000_0001);
+        assert_eq!(serialize(&quiet_f64, true), serialize(&signaling_f64, true));
+        assert_eq!(serialize(&quiet_f64, true), CANONICAL_NAN_F64.to_le_bytes());
+
+        assert!(deserialize::<f64>(&serialize(&signaling_f64, true)).is_nan());
+        // Only NaNs are rewritten.
+        assert_eq!(serialize(&-0.0f64, true), (-0.0f64).to_le_bytes());
+        assert_eq!(serialize(&1.5f32, true), 1.5f32.to_le_bytes());
+    }
+
+    #[test]
+    fn preserves_float_bits_by_default() {
+        for bits in [0x7fc0_0001, 0xffa0_0000, 0x8000_0000, 0x7f80_0000] {
+            let value = f32::from_bits(bits);
+            assert_eq!(
+                deserialize::<f32>(&serialize(&value, false)).to_bits(),
+                bits
+            );
+        }
+        for bits in [
+            0x7ff8_0000_0000_0001,
+            0xfff0_0000_0000_0001,
+            0x8000_0000_0000_0000,
+        ] {
+            let value = f64::from_bits(bits);
+            assert_eq!(
+                deserialize::<f64>(&serialize(&value, false)).to_bits(),
+                bits
+            );
+        }
+    }
+
+    #[test]
+    fn length_prefixes_strings_containing_eot() {
+        let text = format!("before{}after", EOT as char);
+        for lengths in [LengthEncoding::Fixed, LengthEncoding::Varint] {
+            let config = EncodingConfig {
+                strings: StringEncoding::LengthPrefixed,
+                lengths,
+                ..EncodingConfig::default()
+            };
+            let buffer = (&text, 1u8).serialize(Serializer::new(config)).unwrap();
+            assert_eq!(from_bytes(&buffer), Ok((text.clone(), 1u8)), "{lengths:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_eot_in_terminated_strings() {
+        let text = format!("before{}after", EOT as char);
+        assert_eq!(to_bytes(&text), Err(Error::EotInStr));
+        assert_eq!(to_bytes(&"\u{3}"), Err(Error::EotInStr));
+        assert!(to_bytes("no terminator inside").is_ok());
     }
 }
@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    deserializer::{self, Deserializer},
+    serializer::{self, Serializer},
+    EncodingConfig, HEADER_LEN,
+};
+
+const COUNT_SIZE: usize = std::mem::size_of::<u64>();
+
+/// The format header followed by the value count.
+const HEADER_SIZE: usize = HEADER_LEN + COUNT_SIZE;
+
+/// Appends framed values to a buffer, keeping the value count at its start up to date.
+///
+/// The layout is the one `Vec<Vec<u8>>` serializes to: the format header, a count and length
+/// prefixed frames. Frames are serialized without a header of their own.
+pub struct SerializerStream {
+    buffer: Vec<u8>,
+    len: usize,
+}
+
+impl SerializerStream {
+    pub fn new() -> Self {
+        let mut buffer = EncodingConfig::default().header();
+        buffer.resize(HEADER_SIZE, 0);
+        Self { buffer, len: 0 }
+    }
+
+    /// Resumes a stream previously returned by [`SerializerStream::into_bytes`].
+    pub fn from_bytes(buffer: Vec<u8>) -> Result<Self, deserializer::Error> {
+        let header = buffer
+            .get(..HEADER_SIZE)
+            .ok_or(deserializer::Error::EmptyBuffer)?;
+        let len = Deserializer::new(header).parse_len()?;
+        Ok(Self { buffer, len })
+    }
+
+    pub fn push<T>(&mut self, value: &T) -> Result<(), serializer::Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.push_frame(&value.serialize(Serializer::new(EncodingConfig::raw()))?)
+    }
+
+    /// Appends bytes that are already serialized as a single frame.
+    pub fn push_frame(&mut self, frame: &[u8]) -> Result<(), serializer::Error> {
+        self.buffer
+            .extend((frame.len() as u64).serialize(Serializer::new(EncodingConfig::raw()))?);
+        self.buffer.extend_from_slice(frame);
+
+        self.len += 1;
+        let count = (self.len as u64).serialize(Serializer::new(EncodingConfig::raw()))?;
+        self.buffer[HEADER_SIZE - COUNT_SIZE..HEADER_SIZE].copy_from_slice(&count);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+impl Default for SerializerStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads back the values appended by a [`SerializerStream`], in order.
+pub struct DeserializerStream<'a> {
+    deserializer: Deserializer<'a>,
+    remaining: usize,
+}
+
+impl<'a> DeserializerStream<'a> {
+    pub fn new(buffer: &'a [u8]) -> Result<Self, deserializer::Error> {
+        let mut deserializer = Deserializer::new(buffer);
+        let remaining = deserializer.parse_len()?;
+        Ok(Self {
+            deserializer,
+            remaining,
+        })
+    }
+
+    /// Deserializes the next value. After an error the stream ends, since the count it was
+    /// read from can't be trusted anymore.
+    pub fn next_value<T>(&mut self) -> Option<Result<T, deserializer::Error>>
+    where
+        T: Deserialize<'a>,
+    {
+        let value = self.next_frame()?.and_then(|frame| {
+            T::deserialize(Deserializer::with_config(frame, EncodingConfig::raw()))
+        });
+        if value.is_err() {
+            self.remaining = 0;
+        }
+        Some(value)
+    }
+
+    /// Returns the bytes of the next frame without deserializing them, ending the stream after
+    /// an error like [`DeserializerStream::next_value`].
+    pub fn next_frame(&mut self) -> Option<Result<&'a [u8], deserializer::Error>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        let frame = self
+            .deserializer
+            .parse_len()
+            .and_then(|len| self.deserializer.take(len));
+        if frame.is_err() {
+            self.remaining = 0;
+        }
+        Some(frame)
+    }
+
+    /// Number of values not read yet.
+    pub fn len(&self) -> usize {
+        self.remaining
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeserializerStream, SerializerStream};
+    use crate::byte_buffer::to_bytes;
+
+    #[test]
+    fn reads_back_pushed_values() {
+        let mut stream = SerializerStream::new();
+        stream.push(&1u16).unwrap();
+        stream.push("two").unwrap();
+        stream.push_frame(&[3, 4]).unwrap();
+        stream.push(&vec![5u8]).unwrap();
+        assert_eq!(stream.len(), 4);
+        let bytes = stream.into_bytes();
+
+        let mut stream = DeserializerStream::new(&bytes).unwrap();
+        assert_eq!(stream.len(), 4);
+        assert_eq!(stream.next_value(), Some(Ok(1u16)));
+        assert_eq!(stream.next_value(), Some(Ok("two")));
+        assert_eq!(stream.next_frame(), Some(Ok(&[3, 4][..])));
+        assert_eq!(stream.next_value(), Some(Ok(vec![5u8])));
+        assert_eq!(stream.next_value::<u8>(), None);
+        assert!(stream.is_empty());
+    }
+
+    #[test]
+    fn resumes_pushing() {
+        let mut stream = SerializerStream::new();
+        stream.push(&1u8).unwrap();
+        let mut stream = SerializerStream::from_bytes(stream.into_bytes()).unwrap();
+        assert_eq!(stream.len(), 1);
+        stream.push(&2u8).unwrap();
+        let bytes = stream.into_bytes();
+
+        let mut stream = DeserializerStream::new(&bytes).unwrap();
+        assert_eq!(stream.next_value(), Some(Ok(1u8)));
+        assert_eq!(stream.next_value(), Some(Ok(2u8)));
+        assert_eq!(stream.next_value::<u8>(), None);
+    }
+
+    #[test]
+    fn matches_the_layout_of_nested_vecs() {
+        let frames = vec![vec![], vec![1u8], vec![2, 3, 4]];
+        let mut stream = SerializerStream::new();
+        for frame in &frames {
+            stream.push_frame(frame).unwrap();
+        }
+        assert_eq!(stream.into_bytes(), to_bytes(&frames).unwrap());
+        assert_eq!(
+            SerializerStream::new().into_bytes(),
+            to_bytes(&Vec::<Vec<u8>>::new()).unwrap()
+        );
+    }
+
+    #[test]
+    fn ends_after_an_error() {
+        let mut stream = SerializerStream::new();
+        stream.push_frame(b"\xff\x03").unwrap();
+        stream.push("valid").unwrap();
+        let bytes = stream.into_bytes();
+
+        let mut stream = DeserializerStream::new(&bytes).unwrap();
+        assert!(matches!(stream.next_value::<String>(), Some(Err(_))));
+        assert!(stream.is_empty());
+        assert_eq!(stream.next_value::<String>(), None);
+
+        let mut truncated = to_bytes(&vec![vec![1u8, 2]]).unwrap();
+        truncated.pop();
+        let mut stream = DeserializerStream::new(&truncated).unwrap();
+        assert!(matches!(stream.next_frame(), Some(Err(_))));
+        assert_eq!(stream.next_frame(), None);
+    }
+}
@@ -0,0 +1,66 @@
+use std::fmt::Display;
+
+use crate::{
+    byte_buffer::{deserializer, serializer},
+    message::{self, English, Localize, MessageCatalog},
+};
+
+/// Any error returned by the crate, wrapping the error of the layer it came from.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Serialize(serializer::Error),
+    Deserialize(deserializer::Error),
+    /// A pipeline stage couldn't reverse its input, or it was produced by different stages.
+    Stage(String),
+}
+
+impl Localize for Error {
+    /// The code of the wrapped error.
+    fn code(&self) -> &'static str {
+        match self {
+            Self::Serialize(err) => err.code(),
+            Self::Deserialize(err) => err.code(),
+            Self::Stage(_) => "stage",
+        }
+    }
+
+    fn localize(&self, catalog: &dyn MessageCatalog) -> String {
+        match self {
+            Self::Serialize(err) => {
+                message::localize(catalog, "serialize_failed", &[err.localize(catalog)])
+            }
+            Self::Deserialize(err) => {
+                message::localize(catalog, "deserialize_failed", &[err.localize(catalog)])
+            }
+            Self::Stage(msg) => message::localize(catalog, self.code(), std::slice::from_ref(msg)),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.localize(&English))
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Serialize(err) => Some(err),
+            Self::Deserialize(err) => Some(err),
+            Self::Stage(_) => None,
+        }
+    }
+}
+
+impl From<serializer::Error> for Error {
+    fn from(err: serializer::Error) -> Self {
+        Self::Serialize(err)
+    }
+}
+
+impl From<deserializer::Error> for Error {
+    fn from(err: deserializer::Error) -> Self {
+        Self::Deserialize(err)
+    }
+}
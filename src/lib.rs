@@ -1 +1,10 @@
 pub mod byte_buffer;
+pub mod error;
+pub mod lossy;
+pub mod message;
+pub mod packed;
+pub mod payload;
+pub mod pipeline;
+mod varint;
+
+pub use error::Error;
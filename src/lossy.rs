@@ -0,0 +1,212 @@
+//! Reduced-precision wrappers for numeric fields, trading accuracy for payload size.
+//!
+//! Each wrapper changes how a single field is serialized, e.g. `temperature: Fixed<100>` keeps
+//! two decimals in 4 bytes instead of a full f64.
+
+use serde::{ser, Deserialize, Deserializer, Serialize, Serializer};
+
+/// An f32 stored as an IEEE 754 half-precision float in 2 bytes.
+///
+/// Values are rounded to the nearest half, so only about 3 significant decimal digits survive,
+/// and magnitudes above 65504 become infinite.
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+pub struct F16(pub f32);
+
+impl F16 {
+    /// Rounds to nearest, ties to even, like a hardware conversion would.
+    fn to_bits(self) -> u16 {
+        let bits = self.0.to_bits();
+        let sign = ((bits >> 16) & 0x8000) as u16;
+        let exponent = ((bits >> 23) & 0xff) as i32;
+        let mantissa = bits & 0x7f_ffff;
+
+        if exponent == 0xff {
+            let nan = if mantissa != 0 { 0x200 } else { 0 };
+            return sign | 0x7c00 | nan;
+        }
+
+        let exponent = exponent - 127 + 15;
+        if exponent >= 0x1f {
+            return sign | 0x7c00;
+        }
+
+        if exponent < -10 {
+            return sign;
+        }
+
+        // Subnormal halves keep the implicit leading bit in the mantissa and a zero exponent.
+        let (base, mantissa, shift) = if exponent <= 0 {
+            (0, mantissa | 0x80_0000, (14 - exponent) as u32)
+        } else {
+            ((exponent as u32) << 10, mantissa, 13)
+        };
+
+        let half = base | (mantissa >> shift);
+        let rest = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round_up = rest > halfway || (rest == halfway && half & 1 == 1);
+        sign | (half + round_up as u32) as u16
+    }
+
+    fn from_bits(half: u16) -> Self {
+        let sign = u32::from(half & 0x8000) << 16;
+        let exponent = u32::from((half >> 10) & 0x1f);
+        let mantissa = u32::from(half & 0x3ff);
+
+        let bits = match exponent {
+            0 => {
+                let value = mantissa as f32 / (1 << 24) as f32;
+                return Self(if sign != 0 { -value } else { value });
+            }
+            0x1f => sign | 0x7f80_0000 | (mantissa << 13),
+            _ => sign | ((exponent + 127 - 15) << 23) | (mantissa << 13),
+        };
+        Self(f32::from_bits(bits))
+    }
+}
+
+impl From<f32> for F16 {
+    fn from(value: f32) -> Self {
+        Self(value)
+    }
+}
+
+impl From<F16> for f32 {
+    fn from(value: F16) -> Self {
+        value.0
+    }
+}
+
+impl Serialize for F16 {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_u16(self.to_bits())
+    }
+}
+
+impl<'de> Deserialize<'de> for F16 {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        u16::deserialize(deserializer).map(Self::from_bits)
+    }
+}
+
+/// An f64 stored as `round(value * SCALE)` in an i32, so `Fixed<1000>` keeps three decimals.
+///
+/// A SCALE of zero fails to compile. Serializing fails when the scaled value doesn't fit in an
+/// i32 or isn't a number.
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Fixed<const SCALE: u32>(pub f64);
+
+impl<const SCALE: u32> From<f64> for Fixed<SCALE> {
+    fn from(value: f64) -> Self {
+        Self(value)
+    }
+}
+
+impl<const SCALE: u32> From<Fixed<SCALE>> for f64 {
+    fn from(value: Fixed<SCALE>) -> Self {
+        value.0
+    }
+}
+
+impl<const SCALE: u32> Serialize for Fixed<SCALE> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        const { assert!(SCALE != 0, "Fixed needs a non-zero SCALE") };
+
+        let scaled = (self.0 * f64::from(SCALE)).round();
+        if !(f64::from(i32::MIN)..=f64::from(i32::MAX)).contains(&scaled) {
+            return Err(ser::Error::custom(format!(
+                "{} does not fit in a fixed-point value with scale {}",
+                self.0, SCALE
+            )));
+        }
+
+        serializer.serialize_i32(scaled as i32)
+    }
+}
+
+impl<'de, const SCALE: u32> Deserialize<'de> for Fixed<SCALE> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        const { assert!(SCALE != 0, "Fixed needs a non-zero SCALE") };
+
+        let scaled = i32::deserialize(deserializer)?;
+        Ok(Self(f64::from(scaled) / f64::from(SCALE)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fixed, F16};
+    use crate::{
+        byte_buffer::{from_bytes, to_bytes},
+        message::Localize,
+    };
+
+    #[test]
+    fn f16_round_trips_every_half() {
+        for half in 0..=u16::MAX {
+            let value = F16::from_bits(half);
+            let bytes = to_bytes(&value).unwrap();
+            let decoded: F16 = from_bytes(&bytes).unwrap();
+            if value.0.is_nan() {
+                assert!(decoded.0.is_nan(), "{half:#06x}");
+            } else {
+                assert_eq!(decoded.0.to_bits(), value.0.to_bits(), "{half:#06x}");
+                assert_eq!(value.to_bits(), half, "{half:#06x}");
+            }
+        }
+    }
+
+    #[test]
+    fn f16_rounds_to_nearest_even() {
+        for half in 0..0x7bff {
+            let low = F16::from_bits(half).0;
+            let high = F16::from_bits(half + 1).0;
+            let middle = (low + high) / 2.0;
+            let even = if half & 1 == 0 { half } else { half + 1 };
+
+            assert_eq!(F16(middle).to_bits(), even, "{half:#06x}");
+            assert_eq!(F16(-middle).to_bits(), even | 0x8000, "{half:#06x}");
+            assert_eq!(F16(middle.next_down()).to_bits(), half, "{half:#06x}");
+            assert_eq!(F16(middle.next_up()).to_bits(), half + 1, "{half:#06x}");
+        }
+
+        assert_eq!(F16(65519.0).to_bits(), 0x7bff);
+        assert_eq!(F16(65520.0).to_bits(), 0x7c00);
+        assert_eq!(F16(f32::MIN_POSITIVE).to_bits(), 0);
+        assert!(F16::from_bits(F16(f32::NAN).to_bits()).0.is_nan());
+    }
+
+    #[test]
+    fn fixed_round_trips() {
+        for value in [0.0, 1.234, -1.235, 21474.83647, -21474.83648] {
+            let bytes = to_bytes(&Fixed::<100_000>(value)).unwrap();
+            let decoded: Fixed<100_000> = from_bytes(&bytes).unwrap();
+            assert!((decoded.0 - value).abs() <= 0.5 / 100_000.0, "{value}");
+        }
+
+        assert_eq!(
+            from_bytes::<Fixed<1000>>(&to_bytes(&Fixed::<1000>(2.0005)).unwrap()),
+            Ok(Fixed(2.001))
+        );
+    }
+
+    #[test]
+    fn fixed_rejects_values_out_of_range() {
+        for value in [21474.83648, -21474.83649, f64::NAN, f64::INFINITY] {
+            let err = to_bytes(&Fixed::<100_000>(value)).unwrap_err();
+            assert_eq!(err.code(), "custom", "{value}");
+        }
+    }
+}
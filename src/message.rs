@@ -0,0 +1,242 @@
+//! Catalog of the messages errors are displayed with, so they can be translated.
+
+/// Message templates looked up by key, e.g. a translation loaded by the application.
+///
+/// Keys are the [`code`](Localize::code)s of the errors, plus those of the messages wrapping
+/// another one: `located`, `located_path`, `serialize_failed` and `deserialize_failed`. Templates
+/// refer to the arguments of the message as `{0}`, `{1}`, ... Keys the catalog doesn't know fall
+/// back to [`English`].
+pub trait MessageCatalog {
+    fn template(&self, key: &str) -> Option<&str>;
+
+    /// Hint shown for the error with `code`.
+    fn suggestion(&self, _code: &str) -> Option<&str> {
+        None
+    }
+}
+
+/// An error whose message and hint can be looked up in a [`MessageCatalog`].
+pub trait Localize {
+    /// Stable identifier of the error kind, suitable as a message catalog key.
+    fn code(&self) -> &'static str;
+
+    /// The message of the error, in the language of `catalog`.
+    fn localize(&self, catalog: &dyn MessageCatalog) -> String;
+
+    /// Hint on how the caller may recover from the error, if there is one.
+    ///
+    /// Hints are static, one per error code. They don't take the context of the error into
+    /// account, e.g. the offset of a [`Located`](crate::byte_buffer::deserializer::Error::Located)
+    /// error, so they name likely causes rather than the actual one.
+    fn suggestion(&self) -> Option<&'static str> {
+        suggestion(self.code())
+    }
+
+    /// [`Localize::suggestion`] in the language of `catalog`.
+    fn localize_suggestion<'c>(&self, catalog: &'c dyn MessageCatalog) -> Option<&'c str> {
+        catalog
+            .suggestion(self.code())
+            .or_else(|| self.suggestion())
+    }
+}
+
+/// The messages errors are displayed with by default.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct English;
+
+impl MessageCatalog for English {
+    fn template(&self, key: &str) -> Option<&str> {
+        template(key)
+    }
+
+    fn suggestion(&self, code: &str) -> Option<&str> {
+        suggestion(code)
+    }
+}
+
+/// Formats the message `key` of `catalog` with `args`.
+pub(crate) fn localize(catalog: &dyn MessageCatalog, key: &str, args: &[String]) -> String {
+    let template = catalog.template(key).or(template(key)).unwrap_or(key);
+
+    let mut output = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let arg = rest.find('}').and_then(|end| {
+            let index = rest[1..end].parse::<usize>().ok()?;
+            Some((args.get(index)?, end))
+        });
+        match arg {
+            Some((arg, end)) => {
+                output.push_str(arg);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                output.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+fn template(key: &str) -> Option<&'static str> {
+    let template = match key {
+        "custom" => "{0}",
+        "io" => "I/O error: {0}",
+        "unsized_seq" => "sequences must have a known length",
+        "unsized_map" => "maps must have a known length",
+        "eot_in_str" => "EOT terminated strings cannot contain the EOT byte",
+        "deserialize_any" => {
+            "the byte buffer format is not self-describing, the target type must be known"
+        }
+        "wrong_deserialize_type" => "the buffer size does not match the requested type",
+        "eot_not_found" => "string is not terminated by an EOT byte",
+        "empty_buffer" => "unexpected end of buffer",
+        "payload_too_large" => "length does not fit in this target's usize",
+        "invalid_varint" => "varint length is longer than 64 bits",
+        "missing_header" => "buffer does not start with the format header",
+        "unsupported_version" => "format version {0} is not supported",
+        "unsupported_flags" => "encoding flags {0} are not supported",
+        "unknown_tag" => "unknown type tag {0}",
+        "depth_limit_exceeded" => "values are nested too deeply",
+        "stage" => "pipeline stage failed: {0}",
+        "located" => "{0} at byte {1}",
+        "located_path" => "{0} at byte {1}, in `{2}`",
+        "serialize_failed" => "serialization failed: {0}",
+        "deserialize_failed" => "deserialization failed: {0}",
+        _ => return None,
+    };
+    Some(template)
+}
+
+/// English hint for the error with `code`, if there is one. The same for every error of a kind.
+pub(crate) fn suggestion(code: &str) -> Option<&'static str> {
+    let suggestion = match code {
+        "unsized_seq" => "collect the elements into a Vec before serializing",
+        "unsized_map" => "collect the entries into a HashMap or BTreeMap before serializing",
+        "eot_in_str" => "use StringEncoding::LengthPrefixed for arbitrary strings",
+        "deserialize_any" => {
+            "deserialize into a concrete type, or serialize with EncodingConfig::self_describing"
+        }
+        "wrong_deserialize_type" => {
+            "deserialize with the same type the payload was serialized from"
+        }
+        "eot_not_found" | "empty_buffer" => {
+            "the payload may be truncated, check that it was fully extracted"
+        }
+        "payload_too_large" => "deserialize the payload on a 64-bit target",
+        "invalid_varint" => "the payload may be corrupt, check that it was fully extracted",
+        "missing_header" => "set EncodingConfig::raw if the buffer was serialized without a header",
+        "unsupported_version" | "unsupported_flags" => {
+            "update the crate to read payloads written by newer versions"
+        }
+        "unknown_tag" => {
+            "check that the buffer was serialized with EncodingConfig::self_describing"
+        }
+        "depth_limit_exceeded" => {
+            "raise Deserializer::max_depth if the payload is legitimately this deep"
+        }
+        _ => return None,
+    };
+    Some(suggestion)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{localize, English, Localize, MessageCatalog};
+    use crate::byte_buffer::deserializer;
+
+    /// Overrides a couple of messages, leaving the rest to the English fallback.
+    struct Pirate;
+
+    impl MessageCatalog for Pirate {
+        fn template(&self, key: &str) -> Option<&str> {
+            match key {
+                "unknown_tag" => Some("arr, tag {0} be unknown"),
+                "swap" => Some("{1} then {0}"),
+                _ => None,
+            }
+        }
+
+        fn suggestion(&self, code: &str) -> Option<&str> {
+            (code == "empty_buffer").then_some("dig deeper")
+        }
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn substitutes_arguments() {
+        assert_eq!(localize(&Pirate, "swap", &args(&["a", "b"])), "b then a");
+        assert_eq!(
+            localize(&English, "unknown_tag", &args(&["9"])),
+            "unknown type tag 9"
+        );
+        assert_eq!(
+            localize(&Pirate, "unknown_tag", &args(&["9"])),
+            "arr, tag 9 be unknown"
+        );
+    }
+
+    #[test]
+    fn keeps_placeholders_it_cannot_fill() {
+        assert_eq!(localize(&Pirate, "swap", &args(&["a"])), "{1} then a");
+        assert_eq!(
+            localize(&English, "stage", &[]),
+            "pipeline stage failed: {0}"
+        );
+    }
+
+    #[test]
+    fn keeps_stray_braces() {
+        struct Braces;
+
+        impl MessageCatalog for Braces {
+            fn template(&self, key: &str) -> Option<&str> {
+                let template = match key {
+                    "open" => "a { b",
+                    "doubled" => "{{0}}",
+                    "unclosed" => "{0",
+                    "not_an_index" => "{x}{0}",
+                    "reversed" => "}{0}{",
+                    _ => return None,
+                };
+                Some(template)
+            }
+        }
+
+        let values = args(&["x"]);
+        assert_eq!(localize(&Braces, "open", &values), "a { b");
+        assert_eq!(localize(&Braces, "doubled", &values), "{x}");
+        assert_eq!(localize(&Braces, "unclosed", &values), "{0");
+        assert_eq!(localize(&Braces, "not_an_index", &values), "{x}x");
+        assert_eq!(localize(&Braces, "reversed", &values), "}x{");
+    }
+
+    #[test]
+    fn falls_back_to_english() {
+        assert_eq!(
+            localize(&Pirate, "empty_buffer", &[]),
+            "unexpected end of buffer"
+        );
+        assert_eq!(localize(&Pirate, "no_such_key", &[]), "no_such_key");
+
+        let err = deserializer::Error::Located {
+            offset: 3,
+            path: String::new(),
+            source: Box::new(deserializer::Error::UnknownTag(9)),
+        };
+        assert_eq!(err.localize(&Pirate), "arr, tag 9 be unknown at byte 3");
+        assert_eq!(err.localize_suggestion(&Pirate), err.suggestion());
+        assert_eq!(
+            deserializer::Error::EmptyBuffer.localize_suggestion(&Pirate),
+            Some("dig deeper")
+        );
+    }
+}
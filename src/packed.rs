@@ -0,0 +1,543 @@
+//! Compact encodings for numeric sequences, such as telemetry arrays.
+//!
+//! [`BitPacked`] suits small values, [`Delta`] slowly changing ones such as sensor readings and
+//! [`RunLength`] long runs of the same value. None decodes to more than [`MAX_LEN`] values.
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::varint::{self, DecodeError};
+
+/// Most values a sequence may decode to. A few bytes can describe far more values than that, e.g.
+/// a long run or zero-width values, so longer sequences are rejected as corrupt.
+pub const MAX_LEN: usize = 1 << 20;
+
+/// Numbers the sequence wrappers can pack, through their bits as a u64.
+pub trait Packable: Copy {
+    /// Whether values are zigzag encoded before packing, so small negative values take few bits.
+    const SIGNED: bool;
+
+    /// The bits of the value, sign extended for signed integers.
+    fn to_word(self) -> u64;
+
+    /// Inverse of [`Packable::to_word`], truncating to the width of the type.
+    fn from_word(word: u64) -> Self;
+}
+
+macro_rules! impl_packable_int {
+    ($signed:literal: $($ty:ty)*) => {
+        $(
+            impl Packable for $ty {
+                const SIGNED: bool = $signed;
+
+                fn to_word(self) -> u64 {
+                    self as u64
+                }
+
+                fn from_word(word: u64) -> Self {
+                    word as Self
+                }
+            }
+        )*
+    };
+}
+
+impl_packable_int!(true: i8 i16 i32 i64);
+impl_packable_int!(false: u8 u16 u32 u64);
+
+/// Floats are packed through their bit patterns, which stay close for close values of the same
+/// sign, so [`Delta`] suits slowly changing readings.
+impl Packable for f32 {
+    const SIGNED: bool = false;
+
+    fn to_word(self) -> u64 {
+        u64::from(self.to_bits())
+    }
+
+    fn from_word(word: u64) -> Self {
+        Self::from_bits(word as u32)
+    }
+}
+
+impl Packable for f64 {
+    const SIGNED: bool = false;
+
+    fn to_word(self) -> u64 {
+        self.to_bits()
+    }
+
+    fn from_word(word: u64) -> Self {
+        Self::from_bits(word)
+    }
+}
+
+/// Values stored with the bit width of the largest one, e.g. 4 bits each when none exceeds 15.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BitPacked<T>(pub Vec<T>);
+
+/// Values stored as the difference to the previous one, bit packed like [`BitPacked`]. The first
+/// value is stored on its own so it doesn't widen every difference.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Delta<T>(pub Vec<T>);
+
+/// Values stored once per run of repeats, after the lengths of the runs as LEB128 varints.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RunLength<T>(pub Vec<T>);
+
+impl<T: Packable> Serialize for BitPacked<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let words: Vec<_> = self.0.iter().copied().map(encode_word).collect();
+        ByteString(&pack(&words)).serialize(serializer)
+    }
+}
+
+impl<'de, T: Packable> Deserialize<'de> for BitPacked<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut values = vec![];
+        unpack(
+            &ByteBuf::deserialize(deserializer)?.0,
+            &mut values,
+            decode_word,
+        )
+        .map_err(de::Error::custom)?;
+        Ok(Self(values))
+    }
+}
+
+impl<T: Packable> Serialize for Delta<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut bytes = vec![];
+        if let Some(first) = self.0.first() {
+            write_varint(&mut bytes, encode_word(*first));
+            let deltas: Vec<_> = self
+                .0
+                .windows(2)
+                .map(|pair| zigzag(pair[1].to_word().wrapping_sub(pair[0].to_word())))
+                .collect();
+            bytes.extend(pack(&deltas));
+        }
+        ByteString(&bytes).serialize(serializer)
+    }
+}
+
+impl<'de, T: Packable> Deserialize<'de> for Delta<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let bytes = ByteBuf::deserialize(deserializer)?.0;
+        let mut rest = bytes.as_slice();
+        if rest.is_empty() {
+            return Ok(Self(vec![]));
+        }
+
+        let mut previous = read_varint(&mut rest)
+            .map(decode_word::<T>)
+            .map_err(de::Error::custom)?
+            .to_word();
+        let mut values = vec![T::from_word(previous)];
+        unpack(rest, &mut values, |delta| {
+            previous = previous.wrapping_add(unzigzag(delta));
+            T::from_word(previous)
+        })
+        .map_err(de::Error::custom)?;
+        Ok(Self(values))
+    }
+}
+
+impl<T: Serialize + PartialEq> Serialize for RunLength<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut runs: Vec<(u64, &T)> = vec![];
+        for value in &self.0 {
+            match runs.last_mut() {
+                Some((len, last)) if *last == value => *len += 1,
+                _ => runs.push((1, value)),
+            }
+        }
+
+        let mut lengths = vec![];
+        for &(len, _) in &runs {
+            write_varint(&mut lengths, len);
+        }
+        let values: Vec<_> = runs.into_iter().map(|(_, value)| value).collect();
+        (ByteString(&lengths), values).serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de> + Clone> Deserialize<'de> for RunLength<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let (ByteBuf(lengths), runs) = <(ByteBuf, Vec<T>)>::deserialize(deserializer)?;
+        let mut rest = lengths.as_slice();
+        let mut values = vec![];
+        for value in runs {
+            let len = read_varint(&mut rest).map_err(de::Error::custom)?;
+            let len = usize::try_from(len)
+                .ok()
+                .filter(|&len| len > 0 && len <= MAX_LEN - values.len())
+                .ok_or_else(|| de::Error::custom(format!("invalid run length {}", len)))?;
+            values.extend(std::iter::repeat_n(value, len));
+        }
+        if !rest.is_empty() {
+            return Err(de::Error::custom("more run lengths than runs"));
+        }
+        Ok(Self(values))
+    }
+}
+
+/// Bytes serialized as a byte string rather than a sequence, so self-describing buffers tag them
+/// once instead of once per byte.
+struct ByteString<'a>(&'a [u8]);
+
+impl Serialize for ByteString<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+/// Reads back a [`ByteString`], or a sequence of bytes from formats without byte strings.
+struct ByteBuf(Vec<u8>);
+
+impl<'de> Deserialize<'de> for ByteBuf {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = ByteBuf;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("packed bytes")
+            }
+
+            fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<ByteBuf, E> {
+                Ok(ByteBuf(bytes.to_vec()))
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<ByteBuf, E> {
+                Ok(ByteBuf(bytes))
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<ByteBuf, A::Error> {
+                let mut bytes = vec![];
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(ByteBuf(bytes))
+            }
+        }
+
+        deserializer.deserialize_byte_buf(Visitor)
+    }
+}
+
+fn encode_word<T: Packable>(value: T) -> u64 {
+    if T::SIGNED {
+        zigzag(value.to_word())
+    } else {
+        value.to_word()
+    }
+}
+
+fn decode_word<T: Packable>(word: u64) -> T {
+    if T::SIGNED {
+        T::from_word(unzigzag(word))
+    } else {
+        T::from_word(word)
+    }
+}
+
+fn zigzag(word: u64) -> u64 {
+    let value = word as i64;
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(word: u64) -> u64 {
+    (word >> 1) ^ (word & 1).wrapping_neg()
+}
+
+/// Packs `words` as a LEB128 count, the bit width of the largest word and the words with that
+/// width, least significant bit first.
+fn pack(words: &[u64]) -> Vec<u8> {
+    let width = words
+        .iter()
+        .map(|word| u64::BITS - word.leading_zeros())
+        .max()
+        .unwrap_or(0);
+
+    let mut bytes = vec![];
+    write_varint(&mut bytes, words.len() as u64);
+    bytes.push(width as u8);
+
+    let mut bits = 0u128;
+    let mut len = 0;
+    for &word in words {
+        bits |= u128::from(word) << len;
+        len += width;
+        while len >= 8 {
+            bytes.push(bits as u8);
+            bits >>= 8;
+            len -= 8;
+        }
+    }
+    if len > 0 {
+        bytes.push(bits as u8);
+    }
+    bytes
+}
+
+/// Inverse of [`pack`], appending each word to `values` through `decode`.
+fn unpack<T>(
+    bytes: &[u8],
+    values: &mut Vec<T>,
+    mut decode: impl FnMut(u64) -> T,
+) -> Result<(), &'static str> {
+    let mut rest = bytes;
+    let count = read_varint(&mut rest)?;
+    let (&width, rest) = rest.split_first().ok_or("truncated packed sequence")?;
+    if width > 64 {
+        return Err("packed width exceeds 64 bits");
+    }
+    let width = u32::from(width);
+    if (u128::from(count) * u128::from(width)).div_ceil(8) != rest.len() as u128 {
+        return Err("packed sequence length does not match its count");
+    }
+
+    let count = usize::try_from(count)
+        .ok()
+        .filter(|&count| count <= MAX_LEN - values.len())
+        .ok_or("packed sequence is too long")?;
+    values.reserve(count);
+
+    let mask = u64::MAX.checked_shr(64 - width).unwrap_or(0);
+    let mut bits = 0u128;
+    let mut len = 0;
+    let mut rest = rest.iter();
+    for _ in 0..count {
+        while len < width {
+            bits |= u128::from(*rest.next().ok_or("truncated packed sequence")?) << len;
+            len += 8;
+        }
+        values.push(decode(bits as u64 & mask));
+        bits >>= width;
+        len -= width;
+    }
+    Ok(())
+}
+
+fn write_varint(bytes: &mut Vec<u8>, value: u64) {
+    bytes.extend_from_slice(varint::encode(value, &mut [0; varint::MAX_LEN]));
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, &'static str> {
+    varint::decode(bytes).map_err(|error| match error {
+        DecodeError::Truncated => "truncated packed sequence",
+        DecodeError::Overflow => "packed varint is longer than 64 bits",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{de::DeserializeOwned, Serialize};
+
+    use super::{BitPacked, Delta, RunLength, MAX_LEN};
+    use crate::byte_buffer::{
+        deserializer::Error, from_bytes, serializer::Serializer, to_bytes, EncodingConfig,
+    };
+
+    fn round_trip<T>(value: &T) -> T
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        from_bytes(&to_bytes(value).unwrap()).unwrap()
+    }
+
+    /// The message `payload` is rejected with when read as `T`.
+    fn rejection<T, P>(payload: &P) -> String
+    where
+        T: DeserializeOwned + std::fmt::Debug,
+        P: Serialize,
+    {
+        match from_bytes::<T>(&to_bytes(payload).unwrap())
+            .unwrap_err()
+            .kind()
+        {
+            Error::Custom(message) => message.clone(),
+            err => panic!("unexpected error {err:?}"),
+        }
+    }
+
+    /// Varint `count`, then bit `width`, as written by `pack`.
+    fn header(count: u64, width: u8) -> Vec<u8> {
+        let mut bytes = vec![];
+        super::write_varint(&mut bytes, count);
+        bytes.push(width);
+        bytes
+    }
+
+    /// `values` with run `lengths`, as written by [`RunLength`]. Outside self-describing mode
+    /// the Vec of lengths has the layout of their byte string.
+    fn runs(lengths: &[u64], values: Vec<u8>) -> (Vec<u8>, Vec<u8>) {
+        let mut bytes = vec![];
+        for &len in lengths {
+            super::write_varint(&mut bytes, len);
+        }
+        (bytes, values)
+    }
+
+    macro_rules! round_trip_extremes {
+        ($($ty:ident)*) => {
+            $(
+                let values = vec![$ty::MIN, $ty::MAX, 0, 1, $ty::MAX, $ty::MIN];
+                assert_eq!(round_trip(&BitPacked(values.clone())).0, values);
+                assert_eq!(round_trip(&Delta(values.clone())).0, values);
+                assert_eq!(round_trip(&RunLength(values.clone())).0, values);
+            )*
+        };
+    }
+
+    #[test]
+    fn round_trips_empty() {
+        assert_eq!(round_trip(&BitPacked::<u8>(vec![])).0, []);
+        assert_eq!(round_trip(&Delta::<i32>(vec![])).0, []);
+        assert_eq!(round_trip(&RunLength::<u64>(vec![])).0, []);
+    }
+
+    #[test]
+    fn round_trips_extremes() {
+        round_trip_extremes!(i8 i16 i32 i64 u8 u16 u32 u64);
+    }
+
+    #[test]
+    fn round_trips_width_64() {
+        let values = vec![u64::MAX, 0, 1 << 63, 12345];
+        assert_eq!(round_trip(&BitPacked(values.clone())).0, values);
+        // The packed bytes follow the format header and their u64 length.
+        let offset = to_bytes(&()).unwrap().len() + 8;
+        assert_eq!(
+            to_bytes(&BitPacked(values)).unwrap()[offset..offset + 2],
+            [4, 64]
+        );
+
+        let values = vec![f64::MIN_POSITIVE, -0.0, f64::INFINITY, 1.5];
+        assert_eq!(round_trip(&Delta(values.clone())).0, values);
+    }
+
+    #[test]
+    fn round_trips_small_widths() {
+        let values: Vec<u8> = (0..100).map(|n| n % 3).collect();
+        assert_eq!(round_trip(&BitPacked(values.clone())).0, values);
+        assert_eq!(round_trip(&BitPacked(vec![0u16; 50])).0, [0; 50]);
+        assert_eq!(
+            round_trip(&Delta((0..100).collect())).0,
+            (0..100).collect::<Vec<i32>>()
+        );
+    }
+
+    #[test]
+    fn rejects_too_many_values() {
+        let count = MAX_LEN as u64 + 1;
+        assert_eq!(
+            rejection::<BitPacked<u8>, _>(&header(count, 0)),
+            "packed sequence is too long"
+        );
+        assert_eq!(
+            rejection::<Delta<u8>, _>(&[&[0][..], &header(count - 1, 0)].concat()),
+            "packed sequence is too long"
+        );
+        assert_eq!(
+            rejection::<RunLength<u8>, _>(&runs(&[MAX_LEN as u64, 1], vec![1, 2])),
+            "invalid run length 1"
+        );
+        assert_eq!(
+            rejection::<RunLength<u8>, _>(&runs(&[u64::MAX], vec![1])),
+            format!("invalid run length {}", u64::MAX)
+        );
+        assert_eq!(
+            rejection::<RunLength<u8>, _>(&runs(&[0], vec![1])),
+            "invalid run length 0"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        let count_overflow = [&[0xffu8; 9][..], &[0x02, 0]].concat();
+        assert_eq!(
+            rejection::<BitPacked<u8>, _>(&count_overflow),
+            "packed varint is longer than 64 bits"
+        );
+        assert_eq!(
+            rejection::<BitPacked<u8>, _>(&vec![0x80u8]),
+            "truncated packed sequence"
+        );
+        assert_eq!(
+            rejection::<BitPacked<u8>, _>(&header(1, 65)),
+            "packed width exceeds 64 bits"
+        );
+        assert_eq!(
+            rejection::<BitPacked<u8>, _>(&[&header(3, 8)[..], &[1, 2]].concat()),
+            "packed sequence length does not match its count"
+        );
+        assert_eq!(
+            rejection::<RunLength<u8>, _>(&runs(&[1, 1], vec![1])),
+            "more run lengths than runs"
+        );
+        assert_eq!(
+            rejection::<RunLength<u8>, _>(&runs(&[1], vec![1, 2])),
+            "truncated packed sequence"
+        );
+    }
+
+    #[test]
+    fn run_lengths_take_a_byte_per_short_run() {
+        let values: Vec<u8> = (0..100).collect();
+        let plain = to_bytes(&values).unwrap().len();
+        // One varint byte per run, plus the u64 length of the lengths.
+        assert_eq!(to_bytes(&RunLength(values)).unwrap().len(), plain + 8 + 100);
+
+        let repeated = RunLength(vec![7u8; 1000]);
+        assert_eq!(round_trip(&repeated), repeated);
+        assert_eq!(
+            to_bytes(&repeated).unwrap().len(),
+            to_bytes(&vec![7u8]).unwrap().len() + 8 + 2
+        );
+    }
+
+    #[test]
+    fn self_describing_buffers_tag_packed_bytes_once() {
+        let config = EncodingConfig {
+            self_describing: true,
+            ..EncodingConfig::default()
+        };
+        let values: Vec<u16> = (0..1000).map(|n| n * 7 % 3000).collect();
+        let positional = to_bytes(&BitPacked(values.clone())).unwrap();
+        let tagged = BitPacked(values.clone())
+            .serialize(Serializer::new(config))
+            .unwrap();
+        // One tag for the byte string instead of one per byte.
+        assert_eq!(tagged.len(), positional.len() + 1);
+        assert_eq!(from_bytes(&tagged), Ok(BitPacked(values.clone())));
+
+        let runs = RunLength(values);
+        let tagged = runs.serialize(Serializer::new(config)).unwrap();
+        assert_eq!(from_bytes(&tagged), Ok(runs));
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    byte_buffer::{
+        deserializer::{self, Deserializer},
+        serializer::Serializer,
+        stream::{DeserializerStream, SerializerStream},
+        EncodingConfig,
+    },
+    Error,
+};
+
+/// Named entries of different types, serialized together through the byte buffer format.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Payload {
+    entries: BTreeMap<String, Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    media_type: Option<String>,
+    value: Vec<u8>,
+}
+
+impl Payload {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serializes `value` under `name`, replacing any previous entry with that name.
+    pub fn insert<T>(&mut self, name: &str, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.insert_entry(name, None, value)
+    }
+
+    /// Like [`Payload::insert`], tagging the entry with a media type (e.g. `text/plain`) or
+    /// any other custom tag so extraction tools can tell what it holds.
+    pub fn insert_with_media_type<T>(
+        &mut self,
+        name: &str,
+        media_type: &str,
+        value: &T,
+    ) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.insert_entry(name, Some(media_type.to_string()), value)
+    }
+
+    fn insert_entry<T>(
+        &mut self,
+        name: &str,
+        media_type: Option<String>,
+        value: &T,
+    ) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let value = value.serialize(Serializer::new(EncodingConfig::raw()))?;
+        self.entries
+            .insert(name.to_string(), Entry { media_type, value });
+        Ok(())
+    }
+
+    pub fn get<'a, T>(&'a self, name: &str) -> Result<Option<T>, Error>
+    where
+        T: Deserialize<'a>,
+    {
+        self.entries
+            .get(name)
+            .map(|entry| {
+                T::deserialize(Deserializer::with_config(
+                    &entry.value,
+                    EncodingConfig::raw(),
+                ))
+            })
+            .transpose()
+            .map_err(Error::from)
+    }
+
+    pub fn media_type(&self, name: &str) -> Option<&str> {
+        self.entries.get(name)?.media_type.as_deref()
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.entries.remove(name).is_some()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    /// Lists every entry name along with its media type, if it was tagged with one.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.entries
+            .iter()
+            .map(|(name, entry)| (name.as_str(), entry.media_type.as_deref()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut stream = SerializerStream::new();
+        for (name, entry) in &self.entries {
+            stream.push(name)?;
+            stream.push(&entry.media_type)?;
+            stream.push_frame(&entry.value)?;
+        }
+        Ok(stream.into_bytes())
+    }
+
+    pub fn from_bytes(buffer: &[u8]) -> Result<Self, Error> {
+        let mut stream = DeserializerStream::new(buffer)?;
+        let mut entries = BTreeMap::new();
+        while let Some(name) = stream.next_value::<String>() {
+            // An error ends the stream, so it must be returned before the next read hides it.
+            let name = name?;
+            let media_type = stream
+                .next_value::<Option<String>>()
+                .ok_or(deserializer::Error::EmptyBuffer)??;
+            let value = stream
+                .next_frame()
+                .ok_or(deserializer::Error::EmptyBuffer)??
+                .to_vec();
+            entries.insert(name, Entry { media_type, value });
+        }
+        Ok(Self { entries })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Payload;
+    use crate::{
+        byte_buffer::{deserializer, stream::SerializerStream},
+        message::Localize,
+        Error,
+    };
+
+    fn deserialize_error(result: Result<Payload, Error>) -> deserializer::Error {
+        match result {
+            Err(Error::Deserialize(err)) => err,
+            result => panic!("unexpected result {result:?}"),
+        }
+    }
+
+    #[test]
+    fn inserts_gets_and_removes_entries() {
+        let mut payload = Payload::new();
+        payload.insert("count", &3u32).unwrap();
+        payload.insert("name", "first").unwrap();
+        payload.insert("name", "second").unwrap();
+        assert_eq!(payload.len(), 2);
+        assert_eq!(payload.get("count"), Ok(Some(3u32)));
+        assert_eq!(payload.get("name"), Ok(Some("second")));
+        assert_eq!(payload.get::<u32>("missing"), Ok(None));
+
+        assert!(payload.remove("count"));
+        assert!(!payload.remove("count"));
+        assert!(!payload.contains("count"));
+        assert_eq!(payload.get::<u32>("count"), Ok(None));
+        assert_eq!(payload.names().collect::<Vec<_>>(), ["name"]);
+    }
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let mut payload = Payload::new();
+        payload.insert("readings", &vec![1.5f32, -2.0]).unwrap();
+        payload
+            .insert_with_media_type("note", "text/plain", "hello")
+            .unwrap();
+        payload.insert("empty", &()).unwrap();
+
+        let decoded = Payload::from_bytes(&payload.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded, payload);
+        assert_eq!(decoded.media_type("note"), Some("text/plain"));
+        assert_eq!(decoded.media_type("readings"), None);
+        assert_eq!(decoded.get("readings"), Ok(Some(vec![1.5f32, -2.0])));
+        assert_eq!(
+            Payload::from_bytes(&Payload::new().to_bytes().unwrap()),
+            Ok(Payload::new())
+        );
+    }
+
+    #[test]
+    fn rejects_entries_read_as_the_wrong_type() {
+        let mut payload = Payload::new();
+        payload.insert("count", &3u32).unwrap();
+        payload.insert("name", "text").unwrap();
+
+        assert!(matches!(
+            payload.get::<u64>("count"),
+            Err(Error::Deserialize(_))
+        ));
+        assert!(matches!(
+            payload.get::<u16>("count"),
+            Err(Error::Deserialize(_))
+        ));
+        assert!(matches!(
+            payload.get::<u32>("name"),
+            Err(Error::Deserialize(_))
+        ));
+    }
+
+    #[test]
+    fn reports_the_error_of_the_first_bad_frame() {
+        let mut stream = SerializerStream::new();
+        stream.push_frame(b"\xff\x03").unwrap();
+        stream.push(&None::<String>).unwrap();
+        stream.push_frame(&[]).unwrap();
+        let err = deserialize_error(Payload::from_bytes(&stream.into_bytes()));
+        assert_eq!(err.code(), "custom", "{err:?}");
+
+        let mut stream = SerializerStream::new();
+        stream.push("name").unwrap();
+        stream.push_frame(&[7]).unwrap();
+        stream.push_frame(&[]).unwrap();
+        let err = deserialize_error(Payload::from_bytes(&stream.into_bytes()));
+        assert_ne!(err.kind(), &deserializer::Error::EmptyBuffer, "{err:?}");
+    }
+}
@@ -0,0 +1,384 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    byte_buffer::{
+        deserializer::Deserializer,
+        serializer::{self, Serializer},
+        EncodingConfig,
+    },
+    Error,
+};
+
+/// A reversible transform applied to the serialized bytes, such as a compressor or a cipher.
+///
+/// Stages must be `Send + Sync` so a [`Pipeline`] can be shared between threads.
+pub trait Stage: Send + Sync {
+    /// Identifier recorded in the pipeline header, e.g. `xor-v1`. It should change whenever the
+    /// transform does, so bytes are never reversed by a stage that didn't produce them.
+    fn id(&self) -> &str;
+
+    fn forward(&self, bytes: &[u8]) -> Vec<u8>;
+
+    /// Undoes [`Stage::forward`], failing with [`Error::Stage`] when the bytes can't have been
+    /// produced by it.
+    fn reverse(&self, bytes: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// Settings of the stage shown by [`Pipeline::explain`], e.g. a compression level.
+    fn parameters(&self) -> BTreeMap<String, String> {
+        BTreeMap::new()
+    }
+
+    /// Estimated output size for an input of `len` bytes, shown by [`Pipeline::explain`].
+    fn estimate_len(&self, len: usize) -> usize {
+        len
+    }
+}
+
+/// Serializes a value and runs the bytes through each stage in the order they were added.
+///
+/// The output starts with the format header followed by the stage ids. Reversing checks them
+/// against the pipeline's own stages before running those backwards and deserializing the result.
+/// The value itself is serialized without a format header, since the one before the stage ids
+/// already records its encoding, so it is read back with that encoding whatever the reversing
+/// pipeline's configuration.
+#[derive(Default)]
+pub struct Pipeline {
+    config: EncodingConfig,
+    stages: Vec<Box<dyn Stage>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_config(config: EncodingConfig) -> Self {
+        Self {
+            config,
+            stages: vec![],
+        }
+    }
+
+    pub fn stage<S>(mut self, stage: S) -> Self
+    where
+        S: Stage + 'static,
+    {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    pub fn forward<T>(&self, value: &T) -> Result<Vec<u8>, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let bytes = value.serialize(Serializer::new(self.value_config()))?;
+        let bytes = self
+            .stages
+            .iter()
+            .fold(bytes, |bytes, stage| stage.forward(&bytes));
+
+        let mut output = self.header()?;
+        output.extend(bytes);
+        Ok(output)
+    }
+
+    pub fn reverse<T>(&self, bytes: &[u8]) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let mut header = Deserializer::new(bytes);
+        let ids = header.read::<Vec<&str>>()?;
+        if ids != self.ids() {
+            return Err(Error::Stage(format!(
+                "bytes were produced by stages {:?}, but the pipeline has {:?}",
+                ids,
+                self.ids()
+            )));
+        }
+
+        let bytes = self
+            .stages
+            .iter()
+            .rev()
+            .try_fold(header.remaining().to_vec(), |bytes, stage| {
+                stage.reverse(&bytes)
+            })?;
+        let config = EncodingConfig {
+            raw: true,
+            ..header.config()
+        };
+        Ok(T::deserialize(Deserializer::with_config(&bytes, config))?)
+    }
+
+    /// Describes every stage and how it's expected to change the size of a value that serializes
+    /// to `len` bytes, not counting the format header. The result is printable and serializable,
+    /// e.g. to JSON.
+    pub fn explain(&self, len: usize) -> Explanation {
+        let mut stages = vec![];
+        let mut input_len = len;
+        for stage in &self.stages {
+            let output_len = stage.estimate_len(input_len);
+            stages.push(StageExplanation {
+                id: stage.id().to_string(),
+                parameters: stage.parameters(),
+                input_len,
+                output_len,
+            });
+            input_len = output_len;
+        }
+
+        let header_len = self.header().map_or(0, |header| header.len());
+        Explanation {
+            serialized_len: len,
+            stages,
+            header_len,
+            output_len: header_len + input_len,
+        }
+    }
+
+    /// The format header, recording the encoding of the value, followed by the stage ids.
+    fn header(&self) -> Result<Vec<u8>, serializer::Error> {
+        let config = EncodingConfig {
+            raw: false,
+            ..self.config
+        };
+        self.ids().serialize(Serializer::new(config))
+    }
+
+    fn value_config(&self) -> EncodingConfig {
+        EncodingConfig {
+            raw: true,
+            ..self.config
+        }
+    }
+
+    fn ids(&self) -> Vec<&str> {
+        self.stages.iter().map(|stage| stage.id()).collect()
+    }
+}
+
+/// Description of a [`Pipeline`], returned by [`Pipeline::explain`]. Sizes are in bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Explanation {
+    pub serialized_len: usize,
+    pub stages: Vec<StageExplanation>,
+    pub header_len: usize,
+    pub output_len: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StageExplanation {
+    pub id: String,
+    pub parameters: BTreeMap<String, String>,
+    pub input_len: usize,
+    pub output_len: usize,
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "serialize: {} bytes", self.serialized_len)?;
+        for (index, stage) in self.stages.iter().enumerate() {
+            write!(f, "{}. {}", index + 1, stage.id)?;
+            if !stage.parameters.is_empty() {
+                let parameters: Vec<_> = stage
+                    .parameters
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, value))
+                    .collect();
+                write!(f, " ({})", parameters.join(", "))?;
+            }
+            writeln!(
+                f,
+                ": {} -> {} bytes ({:+})",
+                stage.input_len,
+                stage.output_len,
+                stage.output_len as i64 - stage.input_len as i64
+            )?;
+        }
+        writeln!(f, "header: {} bytes", self.header_len)?;
+        write!(f, "total: {} bytes", self.output_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{Explanation, Pipeline, Stage, StageExplanation};
+    use crate::{
+        byte_buffer::{EncodingConfig, LengthEncoding},
+        message::Localize,
+        Error,
+    };
+
+    /// XORs every byte with a key.
+    struct Xor(u8);
+
+    impl Stage for Xor {
+        fn id(&self) -> &str {
+            "xor"
+        }
+
+        fn forward(&self, bytes: &[u8]) -> Vec<u8> {
+            bytes.iter().map(|byte| byte ^ self.0).collect()
+        }
+
+        fn reverse(&self, bytes: &[u8]) -> Result<Vec<u8>, Error> {
+            Ok(self.forward(bytes))
+        }
+
+        fn parameters(&self) -> BTreeMap<String, String> {
+            [("key".to_string(), self.0.to_string())].into()
+        }
+    }
+
+    /// Prepends the length as a single byte, checking it when reversing.
+    struct LengthPrefix;
+
+    impl Stage for LengthPrefix {
+        fn id(&self) -> &str {
+            "len"
+        }
+
+        fn forward(&self, bytes: &[u8]) -> Vec<u8> {
+            [&[bytes.len() as u8], bytes].concat()
+        }
+
+        fn reverse(&self, bytes: &[u8]) -> Result<Vec<u8>, Error> {
+            match bytes.split_first() {
+                Some((&len, rest)) if usize::from(len) == rest.len() => Ok(rest.to_vec()),
+                _ => Err(Error::Stage("length prefix does not match".to_string())),
+            }
+        }
+
+        fn estimate_len(&self, len: usize) -> usize {
+            len + 1
+        }
+    }
+
+    fn pipeline() -> Pipeline {
+        Pipeline::new().stage(Xor(0x5a)).stage(LengthPrefix)
+    }
+
+    #[test]
+    fn reverses_its_stages() {
+        let value = ("text".to_string(), vec![1u32, 2, 3]);
+        let bytes = pipeline().forward(&value).unwrap();
+        assert_eq!(pipeline().reverse(&bytes), Ok(value.clone()));
+
+        let bytes = Pipeline::new().forward(&value).unwrap();
+        assert_eq!(Pipeline::new().reverse(&bytes), Ok(value));
+    }
+
+    #[test]
+    fn runs_stages_in_order() {
+        // 7u16 serializes to [7, 0], XORed and then prefixed with its length.
+        let bytes = pipeline().forward(&7u16).unwrap();
+        let header_len = pipeline().explain(0).header_len;
+        assert_eq!(bytes[header_len..], [2, 7 ^ 0x5a, 0x5a]);
+
+        let bytes = Pipeline::new()
+            .stage(LengthPrefix)
+            .stage(Xor(0x5a))
+            .forward(&7u16)
+            .unwrap();
+        assert_eq!(bytes[header_len..], [2 ^ 0x5a, 7 ^ 0x5a, 0x5a]);
+    }
+
+    #[test]
+    fn reports_stage_errors() {
+        let mut bytes = pipeline().forward(&7u16).unwrap();
+        bytes.push(0);
+        assert_eq!(
+            pipeline().reverse::<u16>(&bytes),
+            Err(Error::Stage("length prefix does not match".to_string()))
+        );
+    }
+
+    #[test]
+    fn reads_the_encoding_from_the_header() {
+        let config = EncodingConfig {
+            lengths: LengthEncoding::Varint,
+            self_describing: true,
+            ..EncodingConfig::default()
+        };
+        let bytes = Pipeline::with_config(config)
+            .forward(&vec![1u8, 2, 3])
+            .unwrap();
+
+        assert_eq!(
+            Pipeline::new().reverse::<Vec<u8>>(&bytes),
+            Ok(vec![1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn rejects_bytes_from_other_stages() {
+        let bytes = pipeline().forward(&7u16).unwrap();
+        for other in [
+            Pipeline::new(),
+            Pipeline::new().stage(Xor(0x5a)),
+            Pipeline::new().stage(LengthPrefix).stage(Xor(0x5a)),
+        ] {
+            let err = other.reverse::<u16>(&bytes).unwrap_err();
+            assert_eq!(err.code(), "stage", "{err:?}");
+        }
+
+        let bytes = Pipeline::new().forward(&7u16).unwrap();
+        assert_eq!(
+            pipeline().reverse::<u16>(&bytes).unwrap_err().code(),
+            "stage"
+        );
+    }
+
+    #[test]
+    fn explains_sizes() {
+        let explanation = pipeline().explain(2);
+        assert_eq!(
+            explanation,
+            Explanation {
+                serialized_len: 2,
+                stages: vec![
+                    StageExplanation {
+                        id: "xor".to_string(),
+                        parameters: [("key".to_string(), "90".to_string())].into(),
+                        input_len: 2,
+                        output_len: 2,
+                    },
+                    StageExplanation {
+                        id: "len".to_string(),
+                        parameters: BTreeMap::new(),
+                        input_len: 2,
+                        output_len: 3,
+                    },
+                ],
+                // Format header, stage count and the two EOT-terminated ids.
+                header_len: 6 + 8 + 4 + 4,
+                output_len: 22 + 3,
+            }
+        );
+        // 7u16 serializes to 2 bytes, so the estimate is exact.
+        assert_eq!(
+            explanation.output_len,
+            pipeline().forward(&7u16).unwrap().len()
+        );
+        assert_eq!(
+            Pipeline::new().explain(2).output_len,
+            Pipeline::new().forward(&7u16).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn displays_explanations() {
+        assert_eq!(
+            pipeline().explain(2).to_string(),
+            "serialize: 2 bytes\n\
+             1. xor (key=90): 2 -> 2 bytes (+0)\n\
+             2. len: 2 -> 3 bytes (+1)\n\
+             header: 22 bytes\n\
+             total: 25 bytes"
+        );
+    }
+}
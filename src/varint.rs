@@ -0,0 +1,49 @@
+//! Unsigned LEB128 varints: 7 bits per byte, least significant first, with the high bit set on
+//! every byte but the last.
+
+/// Bytes taken by the largest u64.
+pub(crate) const MAX_LEN: usize = 10;
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum DecodeError {
+    /// The bytes ended before the last byte of the varint.
+    Truncated,
+    /// The varint encodes a value wider than 64 bits.
+    Overflow,
+}
+
+/// Encodes `value` into `buffer`, returning the bytes used.
+pub(crate) fn encode(mut value: u64, buffer: &mut [u8; MAX_LEN]) -> &[u8] {
+    let mut len = 0;
+    while value >= 0x80 {
+        buffer[len] = value as u8 | 0x80;
+        value >>= 7;
+        len += 1;
+    }
+    buffer[len] = value as u8;
+    &buffer[..=len]
+}
+
+/// Decodes the varint at the start of `bytes`, advancing past it.
+pub(crate) fn decode(bytes: &mut &[u8]) -> Result<u64, DecodeError> {
+    let mut value = 0;
+    for (index, &byte) in bytes.iter().take(MAX_LEN).enumerate() {
+        let shift = 7 * index as u32;
+        let bits = u64::from(byte & 0x7f);
+        if bits << shift >> shift != bits {
+            return Err(DecodeError::Overflow);
+        }
+
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            *bytes = &bytes[index + 1..];
+            return Ok(value);
+        }
+    }
+
+    if bytes.len() >= MAX_LEN {
+        Err(DecodeError::Overflow)
+    } else {
+        Err(DecodeError::Truncated)
+    }
+}